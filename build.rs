@@ -0,0 +1,7 @@
+fn main() {
+    let os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if std::env::var_os("CARGO_FEATURE_GLX").is_some() && os == "linux" {
+        // applications link against libGL.so.1 rather than whatever the file is called
+        println!("cargo:rustc-cdylib-link-arg=-Wl,-soname,libGL.so.1");
+    }
+}
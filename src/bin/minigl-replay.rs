@@ -0,0 +1,200 @@
+//! Replays a trace written with `MINIGL_TRACE` and saves frames of it as images.
+//!
+//! ```text
+//! minigl-replay TRACE [--frames LIST] [--stop CALL] [--out DIR] [--format png|ppm] [--size WxH]
+//! ```
+//!
+//! - `--frames`: the frames to save, as a comma separated list of frame numbers and `first-last`
+//!   ranges with either end optional. Frames are numbered from 0 in the order they were swapped, by
+//!   any context. Defaults to the last frame.
+//! - `--stop`: stop before the call with this number, counting from 0, and also save what the
+//!   current context had drawn so far, from its back buffer if it has one.
+//! - `--out`: the directory to save images in. Defaults to the current directory.
+//! - `--format`: `png` or `ppm`. Defaults to `png`.
+//! - `--size`: the size of contexts created for traces of applications that did not use WGL.
+//!   Defaults to 640x480.
+//!
+//! Frames are saved as `frame-<frame>.png` and the stopping point as `call-<call>.png`. Traces
+//! that never swap, like those of single buffered applications, save what they drew by the end as
+//! if they had been stopped there.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use opengl32::{
+    image::Image, parse_range, replay::Replayer, trace::Reader, ColorBuffer, Framebuffer,
+};
+
+const USAGE: &str = "usage: minigl-replay TRACE [--frames LIST] [--stop CALL] [--out DIR] \
+                     [--format png|ppm] [--size WxH]";
+
+struct Options {
+    trace: PathBuf,
+    /// Inclusive ranges of frames to save, or empty for the last one.
+    frames: Vec<(u64, u64)>,
+    stop: Option<u64>,
+    out: PathBuf,
+    png: bool,
+    size: (usize, usize),
+}
+
+fn parse_frames(list: &str) -> Option<Vec<(u64, u64)>> {
+    list.split(',').map(parse_range).collect()
+}
+
+fn parse_size(size: &str) -> Option<(usize, usize)> {
+    let (width, height) = size.split_once(['x', 'X'])?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args_os().skip(1);
+    let mut trace = None;
+    let mut options = Options {
+        trace: PathBuf::new(),
+        frames: Vec::new(),
+        stop: None,
+        out: PathBuf::from("."),
+        png: true,
+        size: (640, 480),
+    };
+
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.to_str().filter(|arg| arg.starts_with("--")) else {
+            if trace.replace(PathBuf::from(arg)).is_some() {
+                return Err("more than one trace given".into());
+            }
+            continue;
+        };
+        let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+        let text = value.to_str().unwrap_or_default();
+        let invalid = || format!("invalid value for {flag}: {}", value.to_string_lossy());
+        match flag {
+            "--frames" => options.frames = parse_frames(text).ok_or_else(invalid)?,
+            "--stop" => options.stop = Some(text.parse().map_err(|_| invalid())?),
+            "--out" => options.out = PathBuf::from(&value),
+            "--format" => {
+                options.png = match text {
+                    "png" => true,
+                    "ppm" => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "--size" => options.size = parse_size(text).ok_or_else(invalid)?,
+            _ => return Err(format!("unknown option {flag}")),
+        }
+    }
+
+    options.trace = trace.ok_or("no trace given")?;
+    Ok(options)
+}
+
+fn image(fb: &Framebuffer, buffer: ColorBuffer) -> Image {
+    Image::from_bgra(fb.color_buffer(buffer), fb.width, fb.height, fb.width * 4)
+}
+
+/// Saves `image` as `<out>/<name>.png` or `.ppm`.
+fn save(options: &Options, image: &Image, name: &str) -> io::Result<()> {
+    let extension = if options.png { "png" } else { "ppm" };
+    let path = options.out.join(format!("{name}.{extension}"));
+    let mut out = BufWriter::new(File::create(&path)?);
+    if options.png {
+        image.write_png(&mut out)?;
+    } else {
+        image.write_pnm(&mut out)?;
+    }
+    out.flush()?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let input = File::open(&options.trace)?;
+    let mut reader = Reader::new(BufReader::new(input))?;
+    let mut replayer = Replayer::new(options.size);
+    let wanted = |frame: u64| {
+        options
+            .frames
+            .iter()
+            .any(|&(first, last)| (first..=last).contains(&frame))
+    };
+
+    let mut calls = 0;
+    let mut frames = 0;
+    // the number and size of the last frame swapped, and its front buffer, kept for when no frames
+    // were asked for
+    let mut last_frame = None;
+    let mut last_pixels = Vec::new();
+    let mut stopped = false;
+    while let Some(record) = reader.next_record()? {
+        if options.stop == Some(calls) {
+            stopped = true;
+            break;
+        }
+
+        let swapped = replayer
+            .replay(&record)
+            .map_err(|err| io::Error::new(err.kind(), format!("call {calls}: {err}")))?;
+        calls += 1;
+        if swapped {
+            if options.frames.is_empty() {
+                // only copied, since all but the last are thrown away
+                last_frame = replayer.framebuffer(|fb| {
+                    last_pixels.clear();
+                    last_pixels.extend_from_slice(fb.color_buffer(ColorBuffer::Front));
+                    (frames, fb.width, fb.height)
+                });
+            } else if wanted(frames) {
+                if let Some(frame) = replayer.framebuffer(|fb| image(fb, ColorBuffer::Front)) {
+                    save(options, &frame, &format!("frame-{frames:06}"))?;
+                }
+            }
+            frames += 1;
+        }
+    }
+
+    if let Some((number, width, height)) = last_frame {
+        let frame = Image::from_bgra(&last_pixels, width, height, width * 4);
+        save(options, &frame, &format!("frame-{number:06}"))?;
+    }
+    if stopped || frames == 0 {
+        let drawn = replayer.framebuffer(|fb| {
+            let buffer = if fb.format.double_buffer {
+                ColorBuffer::Back
+            } else {
+                ColorBuffer::Front
+            };
+            image(fb, buffer)
+        });
+        match drawn {
+            Some(drawn) => save(options, &drawn, &format!("call-{calls:06}"))?,
+            None => eprintln!("minigl-replay: no context is current at call {calls}"),
+        }
+    } else if let Some(stop) = options.stop {
+        eprintln!("minigl-replay: the trace ended before call {stop}");
+    }
+    println!("replayed {calls} calls and {frames} frames");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("minigl-replay: {err}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("minigl-replay: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Recording swapped frames as video, for captures that do not depend on screen recorders.
+//!
+//! Capture is configured with environment variables, read whenever a context is created:
+//!
+//! - `MINIGL_CAPTURE`: the file to write. If it ends in `.y4m` it is a YUV4MPEG2 stream,
+//!   otherwise the frames are written as raw planar YUV 4:2:0 with no headers at all.
+//! - `MINIGL_CAPTURE_FPS`: the frame rate recorded in the stream, as `N` or `N/D`. Defaults to 60.
+//!
+//! Every swapped frame becomes one video frame, however long it actually took to render, so a
+//! capture plays back at the same speed whatever the machine recording it. Only the first context
+//! to swap a frame with any pixels in it is recorded, which leaves out contexts that are created
+//! just to query the driver. If that context's size changes, later frames are cropped or padded
+//! with black to the size of the first one, since a stream cannot change size.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::sink::{Frame, FrameSink};
+
+/// Set once some context has started recording.
+static CLAIMED: AtomicBool = AtomicBool::new(false);
+
+enum State {
+    /// No frames have been swapped yet.
+    Waiting,
+    Recording {
+        out: BufWriter<File>,
+        width: usize,
+        height: usize,
+    },
+    /// Another context is recording, or writing failed.
+    Stopped,
+}
+
+pub struct CaptureSink {
+    path: PathBuf,
+    y4m: bool,
+    /// The frame rate as a fraction.
+    fps: (u32, u32),
+    state: State,
+    /// Reused between frames to hold the Y, Cb and Cr planes.
+    planes: Vec<u8>,
+}
+
+impl CaptureSink {
+    /// Returns a sink recording the frames of a new context, or `None` if capture is not enabled.
+    pub fn from_env() -> Option<Self> {
+        let path = PathBuf::from(env::var_os("MINIGL_CAPTURE")?);
+        let y4m = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("y4m"));
+        let fps = env::var("MINIGL_CAPTURE_FPS")
+            .ok()
+            .and_then(|fps| parse_fps(&fps))
+            .unwrap_or((60, 1));
+        Some(Self {
+            path,
+            y4m,
+            fps,
+            state: State::Waiting,
+            planes: Vec::new(),
+        })
+    }
+
+    fn start(&mut self, width: usize, height: usize) -> io::Result<State> {
+        if CLAIMED.swap(true, Ordering::Relaxed) {
+            return Ok(State::Stopped);
+        }
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        if self.y4m {
+            // progressive, square pixels, with chroma sited like JPEG's
+            let (numerator, denominator) = self.fps;
+            writeln!(
+                out,
+                "YUV4MPEG2 W{width} H{height} F{numerator}:{denominator} Ip A1:1 C420jpeg"
+            )?;
+        }
+        Ok(State::Recording { out, width, height })
+    }
+
+    fn record(&mut self, frame: &Frame) -> io::Result<()> {
+        if let State::Waiting = self.state {
+            // contexts with nothing to draw to, like surfaceless ones, do not get to record
+            if frame.width == 0 || frame.height == 0 {
+                return Ok(());
+            }
+            self.state = self.start(frame.width, frame.height)?;
+        }
+        let State::Recording { out, width, height } = &mut self.state else {
+            return Ok(());
+        };
+        convert(frame, *width, *height, &mut self.planes);
+        if self.y4m {
+            out.write_all(b"FRAME\n")?;
+        }
+        out.write_all(&self.planes)?;
+        out.flush()
+    }
+}
+
+/// Parses a frame rate given as `N` or `N/D`.
+fn parse_fps(fps: &str) -> Option<(u32, u32)> {
+    let (numerator, denominator) = fps.split_once('/').unwrap_or((fps, "1"));
+    let numerator = numerator.trim().parse().ok()?;
+    let denominator = denominator.trim().parse().ok()?;
+    (numerator > 0 && denominator > 0).then_some((numerator, denominator))
+}
+
+/// Converts a frame to `width` x `height` YUV 4:2:0 planes with the top row first, using BT.601
+/// studio range like other Y4M producers. Pixels outside the frame are black.
+fn convert(frame: &Frame, width: usize, height: usize, planes: &mut Vec<u8>) {
+    let rgb = |x: usize, y: usize| -> [i32; 3] {
+        if x >= frame.width || y >= frame.height {
+            return [0; 3];
+        }
+        // frames are stored bottom row first
+        let offset = (frame.height - 1 - y) * frame.stride + x * 4;
+        let bgra = &frame.pixels[offset..offset + 4];
+        [bgra[2] as i32, bgra[1] as i32, bgra[0] as i32]
+    };
+
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    planes.clear();
+    planes.reserve(width * height + 2 * chroma_width * chroma_height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = rgb(x, y);
+            planes.push((((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8);
+        }
+    }
+
+    // each chroma sample covers a 2x2 block, or what is left of one at odd edges
+    let mut averages = Vec::with_capacity(chroma_width * chroma_height);
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            let (mut sum, mut count) = ([0; 3], 0);
+            for (x, y) in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)] {
+                if x < width && y < height {
+                    let [r, g, b] = rgb(x, y);
+                    sum = [sum[0] + r, sum[1] + g, sum[2] + b];
+                    count += 1;
+                }
+            }
+            averages.push(sum.map(|sum| (sum + count / 2) / count));
+        }
+    }
+    planes.extend(
+        averages
+            .iter()
+            .map(|&[r, g, b]| (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8),
+    );
+    planes.extend(
+        averages
+            .iter()
+            .map(|&[r, g, b]| (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8),
+    );
+}
+
+impl FrameSink for CaptureSink {
+    fn frame(&mut self, frame: &Frame) {
+        if let State::Stopped = self.state {
+            return;
+        }
+        if let Err(err) = self.record(frame) {
+            // the user asked for a capture, so failing silently would only confuse them
+            eprintln!("minigl: cannot capture to {}: {err}", self.path.display());
+            self.state = State::Stopped;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::FrameFormat;
+
+    fn frame(width: usize, height: usize, pixels: &[u8]) -> Frame<'_> {
+        Frame {
+            width,
+            height,
+            stride: width * 4,
+            format: FrameFormat::Bgra8,
+            pixels,
+            depth: &[],
+        }
+    }
+
+    #[test]
+    fn empty_frames_do_not_start_a_recording() {
+        let path = env::temp_dir().join(format!("minigl-capture-{}.y4m", std::process::id()));
+        let mut sink = CaptureSink {
+            path: path.clone(),
+            y4m: true,
+            fps: (30, 1),
+            state: State::Waiting,
+            planes: Vec::new(),
+        };
+        sink.frame(&frame(0, 0, &[]));
+        assert!(matches!(sink.state, State::Waiting));
+        assert!(!CLAIMED.load(Ordering::Relaxed));
+
+        sink.frame(&frame(2, 2, &[255; 16]));
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = b"YUV4MPEG2 W2 H2 F30:1 Ip A1:1 C420jpeg\nFRAME\n";
+        assert!(written.starts_with(header));
+        assert_eq!(written.len(), header.len() + 2 * 2 + 2);
+    }
+}
@@ -0,0 +1,342 @@
+//! Rendering contexts and the per-thread binding of the current one.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, ThreadId},
+};
+
+use crate::{
+    capture::CaptureSink,
+    dump::DumpSink,
+    gamma,
+    pacing::FramePacer,
+    presenter::Presenter,
+    sink::{Frame, FrameFormat, FrameSink, SinkId},
+    GLState,
+};
+
+/// Identifies a context to window system APIs. 0 is never a valid handle.
+pub type Handle = u32;
+
+pub struct Context {
+    pub state: GLState,
+    /// Where the front buffer is shown, once the context has been bound to a drawable.
+    presenter: Option<Box<dyn Presenter>>,
+    pub pacer: FramePacer,
+    /// Everything that is handed each swapped frame.
+    sinks: Vec<(SinkId, Box<dyn FrameSink>)>,
+    /// Sinks taken out of `sinks` while they are handed a frame, which are put back afterwards
+    /// unless they were removed in the meantime.
+    sending: Vec<SinkId>,
+    /// The thread this context is current on, if any.
+    thread: Option<ThreadId>,
+}
+
+impl Context {
+    pub fn new(state: GLState) -> Self {
+        Self {
+            state,
+            presenter: None,
+            pacer: Default::default(),
+            sinks: Vec::new(),
+            sending: Vec::new(),
+            thread: None,
+        }
+    }
+
+    /// Reallocates the framebuffer if the drawable is no longer `width` x `height`.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.state.resize(width, height);
+    }
+
+    /// Binds the context to a drawable, resizing the framebuffer to match it. The first time a
+    /// context is bound, its viewport is also set to cover the drawable.
+    pub fn set_presenter(&mut self, presenter: Box<dyn Presenter>) {
+        if let Some((width, height)) = presenter.size() {
+            self.resize(width, height);
+        }
+        if self.presenter.is_none() {
+            self.state.reset_viewport();
+        }
+        self.presenter = Some(presenter);
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn FrameSink>) -> SinkId {
+        let id = NEXT_SINK.fetch_add(1, Ordering::Relaxed);
+        self.sinks.push((id, sink));
+        id
+    }
+
+    /// Removes a sink added with `add_sink`, returning false if the context has no such sink.
+    pub fn remove_sink(&mut self, id: SinkId) -> bool {
+        let count = self.sinks.len() + self.sending.len();
+        self.sinks.retain(|&(sink, _)| sink != id);
+        self.sending.retain(|&sink| sink != id);
+        self.sinks.len() + self.sending.len() != count
+    }
+
+    /// Takes the sinks out along with a copy of the front buffer to hand them, so that they can
+    /// be run after the context is unlocked.
+    fn take_frame(&mut self) -> Option<SwappedFrame> {
+        if self.sinks.is_empty() {
+            return None;
+        }
+        let sinks = std::mem::take(&mut self.sinks);
+        self.sending.extend(sinks.iter().map(|&(id, _)| id));
+        let fb = &self.state.fb;
+        Some(SwappedFrame {
+            width: fb.width,
+            height: fb.height,
+            pixels: fb.front.clone(),
+            depth: fb.z_buffer.clone(),
+            sinks,
+        })
+    }
+
+    /// Puts back the sinks taken by `take_frame`, ahead of any added since.
+    fn return_sinks(&mut self, mut sinks: Vec<(SinkId, Box<dyn FrameSink>)>) {
+        sinks.retain(|&(id, _)| self.sending.contains(&id));
+        self.sending
+            .retain(|id| !sinks.iter().any(|&(sink, _)| sink == *id));
+        sinks.append(&mut self.sinks);
+        self.sinks = sinks;
+    }
+
+    /// Shows the front buffer on the drawable, with the gamma ramp applied.
+    pub fn present(&mut self) {
+        let fb = &mut self.state.fb;
+        fb.front_dirty = false;
+        if let Some(presenter) = &mut self.presenter {
+            let pixels = gamma::apply(&fb.front);
+            presenter.present(&pixels, fb.width, fb.height);
+        }
+    }
+
+    /// Presents the back buffer, waiting for the swap interval first, and resizes the framebuffer
+    /// for the next frame if the drawable has changed size. Returns the frame for the sinks.
+    fn swap_buffers(&mut self) -> Option<SwappedFrame> {
+        // single buffered contexts have nothing to swap, but presenting what is in the front
+        // buffer does no harm and is what callers that swap anyway expect
+        self.state.fb.swap();
+        self.pacer.wait();
+        let frame = self.take_frame();
+        self.present();
+
+        if let Some((width, height)) = self.presenter.as_ref().and_then(|p| p.size()) {
+            self.resize(width, height);
+        }
+        frame
+    }
+
+    /// Presents the front buffer if anything has been drawn to it since it was last presented,
+    /// which is the only way rendering shows up in single buffered contexts or with
+    /// glDrawBuffer(GL_FRONT).
+    ///
+    /// Every GL call finishes its rendering before returning, so there is no queued work to
+    /// submit first. If that changes, this is where it has to be drained.
+    pub fn flush(&mut self) {
+        if self.state.fb.front_dirty {
+            self.present();
+        }
+    }
+}
+
+/// A frame on its way to the sinks of the context that swapped it.
+struct SwappedFrame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    depth: Vec<f32>,
+    sinks: Vec<(SinkId, Box<dyn FrameSink>)>,
+}
+
+impl SwappedFrame {
+    fn send(mut self, handle: Handle) {
+        let frame = Frame {
+            width: self.width,
+            height: self.height,
+            stride: self.width * 4,
+            format: FrameFormat::Bgra8,
+            pixels: &self.pixels,
+            depth: &self.depth,
+        };
+        for (_, sink) in &mut self.sinks {
+            sink.frame(&frame);
+        }
+        // a sink may have deleted the context, in which case its sinks go with it
+        with_context(handle, |context| context.return_sinks(self.sinks));
+    }
+}
+
+struct Current {
+    handle: Handle,
+    context: Arc<Mutex<Context>>,
+}
+
+static CONTEXTS: Mutex<BTreeMap<Handle, Arc<Mutex<Context>>>> = Mutex::new(BTreeMap::new());
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+static NEXT_SINK: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Current>> = const { RefCell::new(None) };
+}
+
+fn lock(context: &Mutex<Context>) -> MutexGuard<'_, Context> {
+    // a panic while rendering leaves the state no worse than it would be for the next call
+    context.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn with_contexts<R>(f: impl FnOnce(&mut BTreeMap<Handle, Arc<Mutex<Context>>>) -> R) -> R {
+    f(&mut CONTEXTS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Adds a context to the table and returns its handle.
+pub fn create(mut context: Context) -> Handle {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    if let Some(dump) = DumpSink::from_env(handle) {
+        context.add_sink(Box::new(dump));
+    }
+    if let Some(capture) = CaptureSink::from_env() {
+        context.add_sink(Box::new(capture));
+    }
+    with_contexts(|contexts| contexts.insert(handle, Arc::new(Mutex::new(context))));
+    handle
+}
+
+/// Removes a context from the table. Fails if it is current on another thread.
+pub fn delete(handle: Handle) -> bool {
+    let is_current = CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(|current| current.handle == handle)
+    });
+
+    let deleted = with_contexts(|contexts| {
+        let Some(context) = contexts.get(&handle) else {
+            return false;
+        };
+        if lock(context)
+            .thread
+            .is_some_and(|t| t != thread::current().id())
+        {
+            return false;
+        }
+        contexts.remove(&handle);
+        true
+    });
+
+    if deleted && is_current {
+        release_current();
+    }
+    deleted
+}
+
+/// Makes a context current on the calling thread, replacing any previous one, or just releases
+/// the current context if `handle` is 0. The previous context is released even if this fails.
+pub fn make_current(handle: Handle) -> bool {
+    if handle == 0 {
+        release_current();
+        return true;
+    }
+
+    // claimed under the table lock, so that no other thread can claim or delete it meanwhile
+    release_current();
+    let this_thread = thread::current().id();
+    let Some(context) = with_contexts(|contexts| {
+        let context = contexts.get(&handle)?;
+        let mut guard = lock(context);
+        if guard.thread.is_some_and(|t| t != this_thread) {
+            return None;
+        }
+        guard.thread = Some(this_thread);
+        drop(guard);
+        Some(context.clone())
+    }) else {
+        return false;
+    };
+
+    CURRENT.with(|current| *current.borrow_mut() = Some(Current { handle, context }));
+    true
+}
+
+fn release_current() {
+    if let Some(current) = CURRENT.with(|current| current.borrow_mut().take()) {
+        lock(&current.context).thread = None;
+    }
+}
+
+/// Returns the handle of the calling thread's current context.
+pub fn current() -> Option<Handle> {
+    CURRENT.with(|current| current.borrow().as_ref().map(|current| current.handle))
+}
+
+/// Runs `f` on the context with the given handle, whether or not it is current anywhere.
+pub fn with_context<R>(handle: Handle, f: impl FnOnce(&mut Context) -> R) -> Option<R> {
+    let context = with_contexts(|contexts| contexts.get(&handle).cloned())?;
+    let result = f(&mut lock(&context));
+    Some(result)
+}
+
+/// Swaps a context's buffers and hands the new front buffer to its frame sinks. The sinks run with
+/// no locks held, so they are free to make GL calls, on this context or any other.
+pub fn swap_buffers(handle: Handle) {
+    if let Some(Some(frame)) = with_context(handle, |context| context.swap_buffers()) {
+        frame.send(handle);
+    }
+}
+
+/// Swaps the buffers of the calling thread's current context, if it has one.
+pub fn swap_current() {
+    if let Some(handle) = current() {
+        swap_buffers(handle);
+    }
+}
+
+/// Runs `f` on the calling thread's current context. GL calls made without a current context
+/// do nothing, so this returns `R::default()` in that case.
+pub fn with_current<R: Default>(f: impl FnOnce(&mut Context) -> R) -> R {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(current) => f(&mut lock(&current.context)),
+        None => R::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    #[test]
+    fn only_one_thread_can_make_a_context_current() {
+        for _ in 0..200 {
+            let handle = create(Context::new(GLState::new(1, 1, Default::default())));
+            let barrier = Arc::new(Barrier::new(8));
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        let claimed = make_current(handle);
+                        // hold on to it until every thread has tried
+                        barrier.wait();
+                        make_current(0);
+                        claimed
+                    })
+                })
+                .collect();
+            let claimed = threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .filter(|&claimed| claimed)
+                .count();
+            assert_eq!(claimed, 1);
+            assert!(delete(handle));
+        }
+    }
+}
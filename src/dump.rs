@@ -0,0 +1,162 @@
+//! Saving swapped frames as image files, for debugging rendering problems in the field.
+//!
+//! Dumping is configured with environment variables, read whenever a context is created:
+//!
+//! - `MINIGL_DUMP_DIR`: the directory to write to. Nothing is dumped unless this is set.
+//! - `MINIGL_DUMP_FORMAT`: `png`, `ppm` or `png,ppm`. Defaults to `png`.
+//! - `MINIGL_DUMP_FRAMES`: the range of frames to dump, as `first-last` with either end optional,
+//!   or a single frame number. Frames are numbered from 0 by each context. Defaults to all frames.
+//! - `MINIGL_DUMP_EVERY`: dump only every Nth frame of the range. Defaults to 1.
+//! - `MINIGL_DUMP_DEPTH`: if `1`, the depth buffer is also dumped as a grayscale image.
+//!
+//! Files are named `minigl-<context>-<frame>.png`, with `-depth` before the extension for depth
+//! images. Grayscale images are written as PGM rather than PPM.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    context::Handle,
+    image::Image,
+    sink::{Frame, FrameSink},
+};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FileFormat {
+    Png,
+    Pnm,
+}
+
+impl FileFormat {
+    fn extension(self, channels: usize) -> &'static str {
+        match self {
+            FileFormat::Png => "png",
+            FileFormat::Pnm if channels == 1 => "pgm",
+            FileFormat::Pnm => "ppm",
+        }
+    }
+}
+
+pub struct DumpSink {
+    dir: PathBuf,
+    formats: Vec<FileFormat>,
+    first: u64,
+    last: u64,
+    every: u64,
+    depth: bool,
+    context: Handle,
+    /// The number of the next frame.
+    frame: u64,
+}
+
+impl DumpSink {
+    /// Returns a sink for the frames of context `context`, or `None` if dumping is not enabled.
+    pub fn from_env(context: Handle) -> Option<Self> {
+        let dir = PathBuf::from(env::var_os("MINIGL_DUMP_DIR")?);
+
+        let formats = match env::var("MINIGL_DUMP_FORMAT") {
+            Ok(formats) => formats
+                .split(',')
+                .filter_map(|format| match format.trim().to_ascii_lowercase().as_str() {
+                    "png" => Some(FileFormat::Png),
+                    "ppm" | "pnm" => Some(FileFormat::Pnm),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => vec![FileFormat::Png],
+        };
+
+        let (first, last) = env::var("MINIGL_DUMP_FRAMES")
+            .ok()
+            .and_then(|range| parse_range(&range))
+            .unwrap_or((0, u64::MAX));
+        let every = env::var("MINIGL_DUMP_EVERY")
+            .ok()
+            .and_then(|every| every.trim().parse().ok())
+            .filter(|&every| every > 0)
+            .unwrap_or(1);
+        let depth = env::var("MINIGL_DUMP_DEPTH").is_ok_and(|depth| depth.trim() == "1");
+
+        Some(Self {
+            dir,
+            formats,
+            first,
+            last,
+            every,
+            depth,
+            context,
+            frame: 0,
+        })
+    }
+
+    fn wants(&self, frame: u64) -> bool {
+        (self.first..=self.last).contains(&frame) && (frame - self.first).is_multiple_of(self.every)
+    }
+
+    fn write(&self, image: &Image, suffix: &str) -> io::Result<()> {
+        for &format in &self.formats {
+            let name = format!(
+                "minigl-{}-{:06}{suffix}.{}",
+                self.context,
+                self.frame,
+                format.extension(image.channels)
+            );
+            let mut out = BufWriter::new(File::create(self.dir.join(name))?);
+            match format {
+                FileFormat::Png => image.write_png(&mut out)?,
+                FileFormat::Pnm => image.write_pnm(&mut out)?,
+            }
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn dump(&self, frame: &Frame) -> io::Result<()> {
+        let color = Image::from_bgra(frame.pixels, frame.width, frame.height, frame.stride);
+        self.write(&color, "")?;
+        if self.depth {
+            let depth = Image::from_depth(frame.depth, frame.width, frame.height);
+            self.write(&depth, "-depth")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an inclusive range of frame numbers given as `first-last`, `first-`, `-last` or a single
+/// frame number.
+pub fn parse_range(range: &str) -> Option<(u64, u64)> {
+    let bound = |s: &str, default| match s.trim() {
+        "" => Some(default),
+        s => s.parse().ok(),
+    };
+    match range.split_once('-') {
+        Some((first, last)) => Some((bound(first, 0)?, bound(last, u64::MAX)?)),
+        None => {
+            let frame = range.trim().parse().ok()?;
+            Some((frame, frame))
+        }
+    }
+}
+
+impl FrameSink for DumpSink {
+    fn frame(&mut self, frame: &Frame) {
+        // an empty frame, from a context with nothing to draw to, makes no valid image
+        let empty = frame.width == 0 || frame.height == 0;
+        if self.wants(self.frame) && !empty {
+            if let Err(err) = self.dump(frame) {
+                // the user asked for these files, so failing silently would only confuse them
+                eprintln!(
+                    "minigl: cannot dump frames to {}: {err}",
+                    self.dir.display()
+                );
+                self.formats.clear();
+                self.depth = false;
+            }
+        }
+        self.frame += 1;
+    }
+}
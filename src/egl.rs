@@ -0,0 +1,670 @@
+//! An EGL front end for headless rendering with no display server.
+//!
+//! There is a single display, which every native display and the surfaceless platform map to, and
+//! the only surfaces are pbuffers. A pbuffer's pixels are the framebuffer of the context bound to
+//! it, so they do not survive binding the context to a different surface.
+
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    ffi::{c_char, c_void},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    context::{self, Context, Handle},
+    format::{self, PixelFormat},
+    glFinish,
+    presenter::Presenter,
+    procs, GLState,
+};
+
+type EGLBoolean = u32;
+type EGLint = i32;
+type EGLenum = u32;
+type EGLDisplay = *mut c_void;
+type EGLConfig = *mut c_void;
+type EGLSurface = *mut c_void;
+type EGLContext = *mut c_void;
+
+const EGL_FALSE: EGLBoolean = 0;
+const EGL_TRUE: EGLBoolean = 1;
+
+const EGL_DONT_CARE: EGLint = -1;
+
+const EGL_SUCCESS: EGLint = 0x3000;
+const EGL_NOT_INITIALIZED: EGLint = 0x3001;
+const EGL_BAD_ACCESS: EGLint = 0x3002;
+const EGL_BAD_ATTRIBUTE: EGLint = 0x3004;
+const EGL_BAD_CONFIG: EGLint = 0x3005;
+const EGL_BAD_CONTEXT: EGLint = 0x3006;
+const EGL_BAD_DISPLAY: EGLint = 0x3008;
+const EGL_BAD_MATCH: EGLint = 0x3009;
+const EGL_BAD_PARAMETER: EGLint = 0x300c;
+const EGL_BAD_SURFACE: EGLint = 0x300d;
+
+const EGL_BUFFER_SIZE: EGLint = 0x3020;
+const EGL_ALPHA_SIZE: EGLint = 0x3021;
+const EGL_BLUE_SIZE: EGLint = 0x3022;
+const EGL_GREEN_SIZE: EGLint = 0x3023;
+const EGL_RED_SIZE: EGLint = 0x3024;
+const EGL_DEPTH_SIZE: EGLint = 0x3025;
+const EGL_STENCIL_SIZE: EGLint = 0x3026;
+const EGL_CONFIG_CAVEAT: EGLint = 0x3027;
+const EGL_CONFIG_ID: EGLint = 0x3028;
+const EGL_LEVEL: EGLint = 0x3029;
+const EGL_MAX_PBUFFER_HEIGHT: EGLint = 0x302a;
+const EGL_MAX_PBUFFER_PIXELS: EGLint = 0x302b;
+const EGL_MAX_PBUFFER_WIDTH: EGLint = 0x302c;
+const EGL_NATIVE_RENDERABLE: EGLint = 0x302d;
+const EGL_SAMPLES: EGLint = 0x3031;
+const EGL_SAMPLE_BUFFERS: EGLint = 0x3032;
+const EGL_SURFACE_TYPE: EGLint = 0x3033;
+const EGL_TRANSPARENT_TYPE: EGLint = 0x3034;
+const EGL_NONE: EGLint = 0x3038;
+const EGL_MIN_SWAP_INTERVAL: EGLint = 0x303b;
+const EGL_MAX_SWAP_INTERVAL: EGLint = 0x303c;
+const EGL_COLOR_BUFFER_TYPE: EGLint = 0x303f;
+const EGL_RENDERABLE_TYPE: EGLint = 0x3040;
+const EGL_CONFORMANT: EGLint = 0x3042;
+
+const EGL_PBUFFER_BIT: EGLint = 0x0001;
+const EGL_OPENGL_BIT: EGLint = 0x0008;
+const EGL_RGB_BUFFER: EGLint = 0x308e;
+
+const EGL_VENDOR: EGLint = 0x3053;
+const EGL_VERSION: EGLint = 0x3054;
+const EGL_EXTENSIONS: EGLint = 0x3055;
+const EGL_CLIENT_APIS: EGLint = 0x308d;
+
+const EGL_HEIGHT: EGLint = 0x3056;
+const EGL_WIDTH: EGLint = 0x3057;
+const EGL_LARGEST_PBUFFER: EGLint = 0x3058;
+
+const EGL_DRAW: EGLint = 0x3059;
+const EGL_READ: EGLint = 0x305a;
+
+const EGL_OPENGL_API: EGLenum = 0x30a2;
+const EGL_CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+const EGL_CONTEXT_MINOR_VERSION: EGLint = 0x30fb;
+
+const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31dd;
+
+/// The largest pbuffer eglCreatePbufferSurface accepts in either dimension.
+const MAX_PBUFFER_SIZE: EGLint = 16384;
+
+/// The one display, which cannot be null since that is EGL_NO_DISPLAY.
+const DISPLAY: EGLDisplay = 1 as EGLDisplay;
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+struct Pbuffer {
+    config: usize,
+    width: usize,
+    height: usize,
+}
+
+static SURFACES: Mutex<BTreeMap<u32, Pbuffer>> = Mutex::new(BTreeMap::new());
+static NEXT_SURFACE: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    static ERROR: Cell<EGLint> = const { Cell::new(EGL_SUCCESS) };
+    /// The surface passed to the last successful eglMakeCurrent on this thread.
+    static CURRENT_SURFACE: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Records `error` for eglGetError and returns `value`.
+fn fail<T>(error: EGLint, value: T) -> T {
+    ERROR.with(|e| e.set(error));
+    value
+}
+
+fn succeed<T>(value: T) -> T {
+    fail(EGL_SUCCESS, value)
+}
+
+/// Checks that `display` is the initialized display, recording an error otherwise.
+fn check_display(display: EGLDisplay) -> bool {
+    if display != DISPLAY {
+        return fail(EGL_BAD_DISPLAY, false);
+    }
+    if !INITIALIZED.load(Ordering::Relaxed) {
+        return fail(EGL_NOT_INITIALIZED, false);
+    }
+    true
+}
+
+/// Every config, as the index of its format in `format::supported()`. Pbuffers never swap, so
+/// only single buffered formats are offered.
+fn configs() -> impl Iterator<Item = (usize, PixelFormat)> {
+    format::supported()
+        .enumerate()
+        .filter(|(_, format)| !format.double_buffer)
+}
+
+/// Config handles are 1-based format indices, which double as config IDs.
+fn config(handle: EGLConfig) -> Option<(usize, PixelFormat)> {
+    let index = (handle as usize).checked_sub(1)?;
+    configs().find(|&(i, _)| i == index)
+}
+
+fn config_handle(index: usize) -> EGLConfig {
+    (index + 1) as EGLConfig
+}
+
+fn config_attribute(index: usize, format: PixelFormat, attribute: EGLint) -> Option<EGLint> {
+    Some(match attribute {
+        EGL_BUFFER_SIZE => format.color_bits as EGLint,
+        EGL_RED_SIZE | EGL_GREEN_SIZE | EGL_BLUE_SIZE => 8,
+        EGL_ALPHA_SIZE => format.alpha_bits as EGLint,
+        EGL_DEPTH_SIZE => format.depth_bits as EGLint,
+        EGL_STENCIL_SIZE => format.stencil_bits as EGLint,
+        EGL_CONFIG_ID => index as EGLint + 1,
+        EGL_CONFIG_CAVEAT | EGL_TRANSPARENT_TYPE => EGL_NONE,
+        EGL_LEVEL | EGL_SAMPLES | EGL_SAMPLE_BUFFERS | EGL_NATIVE_RENDERABLE => 0,
+        EGL_MAX_PBUFFER_WIDTH | EGL_MAX_PBUFFER_HEIGHT => MAX_PBUFFER_SIZE,
+        EGL_MAX_PBUFFER_PIXELS => MAX_PBUFFER_SIZE * MAX_PBUFFER_SIZE,
+        EGL_MIN_SWAP_INTERVAL => 0,
+        EGL_MAX_SWAP_INTERVAL => 1,
+        EGL_SURFACE_TYPE => EGL_PBUFFER_BIT,
+        EGL_RENDERABLE_TYPE | EGL_CONFORMANT => EGL_OPENGL_BIT,
+        EGL_COLOR_BUFFER_TYPE => EGL_RGB_BUFFER,
+        _ => return None,
+    })
+}
+
+/// Returns the attribute/value pairs of an EGL_NONE terminated list.
+fn attribute_pairs(mut list: *const EGLint) -> Vec<(EGLint, EGLint)> {
+    let mut pairs = Vec::new();
+    if list.is_null() {
+        return pairs;
+    }
+    unsafe {
+        while *list != EGL_NONE {
+            pairs.push((*list, *list.add(1)));
+            list = list.add(2);
+        }
+    }
+    pairs
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetError() -> EGLint {
+    ERROR.with(|e| e.replace(EGL_SUCCESS))
+}
+
+/// Every native display maps to the same display, since nothing is ever shown on it.
+#[no_mangle]
+pub extern "system" fn eglGetDisplay(_native_display: *mut c_void) -> EGLDisplay {
+    succeed(DISPLAY)
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetPlatformDisplayEXT(
+    platform: EGLenum,
+    _native_display: *mut c_void,
+    _attributes: *const EGLint,
+) -> EGLDisplay {
+    if platform != EGL_PLATFORM_SURFACELESS_MESA {
+        return fail(EGL_BAD_PARAMETER, std::ptr::null_mut());
+    }
+    succeed(DISPLAY)
+}
+
+#[no_mangle]
+pub extern "system" fn eglInitialize(
+    display: EGLDisplay,
+    major: *mut EGLint,
+    minor: *mut EGLint,
+) -> EGLBoolean {
+    if display != DISPLAY {
+        return fail(EGL_BAD_DISPLAY, EGL_FALSE);
+    }
+    INITIALIZED.store(true, Ordering::Relaxed);
+    for (version, value) in [(major, 1), (minor, 4)] {
+        if let Some(version) = unsafe { version.as_mut() } {
+            *version = value;
+        }
+    }
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglTerminate(display: EGLDisplay) -> EGLBoolean {
+    if display != DISPLAY {
+        return fail(EGL_BAD_DISPLAY, EGL_FALSE);
+    }
+    INITIALIZED.store(false, Ordering::Relaxed);
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglQueryString(display: EGLDisplay, name: EGLint) -> *const c_char {
+    // client extensions are queried without a display
+    if display.is_null() && name == EGL_EXTENSIONS {
+        return succeed(c"EGL_EXT_platform_base EGL_MESA_platform_surfaceless".as_ptr());
+    }
+    if !check_display(display) {
+        return std::ptr::null();
+    }
+    succeed(match name {
+        EGL_VENDOR => c"minigl".as_ptr(),
+        EGL_VERSION => c"1.4 minigl".as_ptr(),
+        EGL_EXTENSIONS => c"EGL_KHR_surfaceless_context".as_ptr(),
+        EGL_CLIENT_APIS => c"OpenGL".as_ptr(),
+        _ => return fail(EGL_BAD_PARAMETER, std::ptr::null()),
+    })
+}
+
+/// Copies as many of `matches` into `configs` as fit, or just counts them if `configs` is null.
+fn return_configs(
+    matches: impl Iterator<Item = usize>,
+    configs: *mut EGLConfig,
+    config_size: EGLint,
+    num_config: *mut EGLint,
+) -> EGLBoolean {
+    if num_config.is_null() {
+        return fail(EGL_BAD_PARAMETER, EGL_FALSE);
+    }
+    let mut count = 0;
+    for index in matches {
+        if !configs.is_null() {
+            if count >= config_size {
+                break;
+            }
+            unsafe { *configs.add(count as usize) = config_handle(index) };
+        }
+        count += 1;
+    }
+    unsafe { *num_config = count };
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetConfigs(
+    display: EGLDisplay,
+    configs: *mut EGLConfig,
+    config_size: EGLint,
+    num_config: *mut EGLint,
+) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    return_configs(
+        self::configs().map(|(index, _)| index),
+        configs,
+        config_size,
+        num_config,
+    )
+}
+
+#[no_mangle]
+pub extern "system" fn eglChooseConfig(
+    display: EGLDisplay,
+    attributes: *const EGLint,
+    configs: *mut EGLConfig,
+    config_size: EGLint,
+    num_config: *mut EGLint,
+) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+
+    let requested = attribute_pairs(attributes);
+    let matches = |index: usize, format: PixelFormat| {
+        requested.iter().all(|&(attribute, value)| {
+            if value == EGL_DONT_CARE {
+                return true;
+            }
+            let Some(actual) = config_attribute(index, format, attribute) else {
+                // attributes minigl has nothing to say about are satisfied by default
+                return true;
+            };
+            match attribute {
+                EGL_BUFFER_SIZE | EGL_RED_SIZE | EGL_GREEN_SIZE | EGL_BLUE_SIZE
+                | EGL_ALPHA_SIZE | EGL_DEPTH_SIZE | EGL_STENCIL_SIZE | EGL_SAMPLES
+                | EGL_SAMPLE_BUFFERS => actual >= value,
+                EGL_SURFACE_TYPE | EGL_RENDERABLE_TYPE | EGL_CONFORMANT => actual & value == value,
+                EGL_MAX_PBUFFER_WIDTH | EGL_MAX_PBUFFER_HEIGHT | EGL_MAX_PBUFFER_PIXELS => true,
+                _ => actual == value,
+            }
+        })
+    };
+
+    let mut found: Vec<_> = self::configs()
+        .filter(|&(index, format)| matches(index, format))
+        .collect();
+    // EGL sorts by more color bits first if any were asked for, then smaller buffers
+    let wants_alpha = requested
+        .iter()
+        .any(|&(attribute, value)| attribute == EGL_ALPHA_SIZE && value > 0);
+    found.sort_by_key(|&(index, format)| {
+        (
+            std::cmp::Reverse(if wants_alpha { format.alpha_bits } else { 0 }),
+            format.color_bits,
+            format.depth_bits,
+            format.stencil_bits,
+            index,
+        )
+    });
+    return_configs(
+        found.into_iter().map(|(index, _)| index),
+        configs,
+        config_size,
+        num_config,
+    )
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetConfigAttrib(
+    display: EGLDisplay,
+    config: EGLConfig,
+    attribute: EGLint,
+    value: *mut EGLint,
+) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    let Some((index, format)) = self::config(config) else {
+        return fail(EGL_BAD_CONFIG, EGL_FALSE);
+    };
+    let Some(result) = config_attribute(index, format, attribute) else {
+        return fail(EGL_BAD_ATTRIBUTE, EGL_FALSE);
+    };
+    if value.is_null() {
+        return fail(EGL_BAD_PARAMETER, EGL_FALSE);
+    }
+    unsafe { *value = result };
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglBindAPI(api: EGLenum) -> EGLBoolean {
+    if api != EGL_OPENGL_API {
+        return fail(EGL_BAD_PARAMETER, EGL_FALSE);
+    }
+    succeed(EGL_TRUE)
+}
+
+/// Desktop GL is the only API, so it is always the bound one.
+#[no_mangle]
+pub extern "system" fn eglQueryAPI() -> EGLenum {
+    EGL_OPENGL_API
+}
+
+#[no_mangle]
+pub extern "system" fn eglCreatePbufferSurface(
+    display: EGLDisplay,
+    config: EGLConfig,
+    attributes: *const EGLint,
+) -> EGLSurface {
+    if !check_display(display) {
+        return std::ptr::null_mut();
+    }
+    let Some((index, _)) = self::config(config) else {
+        return fail(EGL_BAD_CONFIG, std::ptr::null_mut());
+    };
+
+    let (mut width, mut height) = (0, 0);
+    for (attribute, value) in attribute_pairs(attributes) {
+        match attribute {
+            EGL_WIDTH => width = value,
+            EGL_HEIGHT => height = value,
+            EGL_LARGEST_PBUFFER => {}
+            _ => return fail(EGL_BAD_ATTRIBUTE, std::ptr::null_mut()),
+        }
+    }
+    if !(0..=MAX_PBUFFER_SIZE).contains(&width) || !(0..=MAX_PBUFFER_SIZE).contains(&height) {
+        return fail(EGL_BAD_PARAMETER, std::ptr::null_mut());
+    }
+
+    let handle = NEXT_SURFACE.fetch_add(1, Ordering::Relaxed);
+    let mut surfaces = SURFACES.lock().unwrap_or_else(|e| e.into_inner());
+    surfaces.insert(
+        handle,
+        Pbuffer {
+            config: index,
+            width: width as usize,
+            height: height as usize,
+        },
+    );
+    succeed(handle as usize as EGLSurface)
+}
+
+#[no_mangle]
+pub extern "system" fn eglDestroySurface(display: EGLDisplay, surface: EGLSurface) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    let mut surfaces = SURFACES.lock().unwrap_or_else(|e| e.into_inner());
+    match surfaces.remove(&(surface as usize as u32)) {
+        Some(_) => succeed(EGL_TRUE),
+        None => fail(EGL_BAD_SURFACE, EGL_FALSE),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn eglQuerySurface(
+    display: EGLDisplay,
+    surface: EGLSurface,
+    attribute: EGLint,
+    value: *mut EGLint,
+) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    let surfaces = SURFACES.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(pbuffer) = surfaces.get(&(surface as usize as u32)) else {
+        return fail(EGL_BAD_SURFACE, EGL_FALSE);
+    };
+    let result = match attribute {
+        EGL_WIDTH => pbuffer.width as EGLint,
+        EGL_HEIGHT => pbuffer.height as EGLint,
+        EGL_CONFIG_ID => pbuffer.config as EGLint + 1,
+        EGL_LARGEST_PBUFFER => EGL_FALSE as EGLint,
+        _ => return fail(EGL_BAD_ATTRIBUTE, EGL_FALSE),
+    };
+    if value.is_null() {
+        return fail(EGL_BAD_PARAMETER, EGL_FALSE);
+    }
+    unsafe { *value = result };
+    succeed(EGL_TRUE)
+}
+
+fn handle(context: EGLContext) -> Handle {
+    context as usize as Handle
+}
+
+#[no_mangle]
+pub extern "system" fn eglCreateContext(
+    display: EGLDisplay,
+    config: EGLConfig,
+    share_context: EGLContext,
+    attributes: *const EGLint,
+) -> EGLContext {
+    if !check_display(display) {
+        return std::ptr::null_mut();
+    }
+    let Some((_, format)) = self::config(config) else {
+        return fail(EGL_BAD_CONFIG, std::ptr::null_mut());
+    };
+    for (attribute, value) in attribute_pairs(attributes) {
+        match attribute {
+            EGL_CONTEXT_MAJOR_VERSION if value > 1 => {
+                return fail(EGL_BAD_MATCH, std::ptr::null_mut())
+            }
+            EGL_CONTEXT_MAJOR_VERSION | EGL_CONTEXT_MINOR_VERSION => {}
+            _ => return fail(EGL_BAD_ATTRIBUTE, std::ptr::null_mut()),
+        }
+    }
+
+    let mut state = GLState::new(0, 0, format);
+    if !share_context.is_null() {
+        match context::with_context(handle(share_context), |context| {
+            context.state.namespace.clone()
+        }) {
+            Some(namespace) => state.namespace = namespace,
+            None => return fail(EGL_BAD_CONTEXT, std::ptr::null_mut()),
+        }
+    }
+    let mut context = Context::new(state);
+    // nothing is displayed, so swaps are only paced if asked for with eglSwapInterval
+    context.pacer.interval = 0;
+    succeed(context::create(context) as usize as EGLContext)
+}
+
+#[no_mangle]
+pub extern "system" fn eglDestroyContext(display: EGLDisplay, context: EGLContext) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    if !context::delete(handle(context)) {
+        return fail(EGL_BAD_CONTEXT, EGL_FALSE);
+    }
+    succeed(EGL_TRUE)
+}
+
+/// Sizes a context's framebuffer to the pbuffer it is bound to. There is nothing to present to.
+struct PbufferPresenter {
+    width: usize,
+    height: usize,
+}
+
+impl Presenter for PbufferPresenter {
+    fn size(&self) -> Option<(usize, usize)> {
+        Some((self.width, self.height))
+    }
+
+    fn present(&mut self, _pixels: &[u8], _width: usize, _height: usize) {}
+}
+
+/// Binds `context` to `draw`, which must also be `read` since a context has one framebuffer.
+/// Surfaceless contexts get an empty framebuffer.
+#[no_mangle]
+pub extern "system" fn eglMakeCurrent(
+    display: EGLDisplay,
+    draw: EGLSurface,
+    read: EGLSurface,
+    context: EGLContext,
+) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    let handle = handle(context);
+    let surface = draw as usize as u32;
+    if draw != read || (handle == 0 && surface != 0) {
+        return fail(EGL_BAD_MATCH, EGL_FALSE);
+    }
+
+    let size = if surface == 0 {
+        (0, 0)
+    } else {
+        let surfaces = SURFACES.lock().unwrap_or_else(|e| e.into_inner());
+        match surfaces.get(&surface) {
+            Some(pbuffer) => (pbuffer.width, pbuffer.height),
+            None => return fail(EGL_BAD_SURFACE, EGL_FALSE),
+        }
+    };
+
+    let unchanged =
+        context::current() == Some(handle) && CURRENT_SURFACE.with(|s| s.get()) == surface;
+    if !context::make_current(handle) {
+        return fail(EGL_BAD_ACCESS, EGL_FALSE);
+    }
+    if handle != 0 && !unchanged {
+        let (width, height) = size;
+        let presenter = PbufferPresenter { width, height };
+        context::with_current(|context| context.set_presenter(Box::new(presenter)));
+    }
+    CURRENT_SURFACE.with(|s| s.set(surface));
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetCurrentContext() -> EGLContext {
+    context::current().unwrap_or(0) as usize as EGLContext
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetCurrentDisplay() -> EGLDisplay {
+    match context::current() {
+        Some(_) => DISPLAY,
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetCurrentSurface(which: EGLint) -> EGLSurface {
+    if which != EGL_DRAW && which != EGL_READ {
+        return fail(EGL_BAD_PARAMETER, std::ptr::null_mut());
+    }
+    // destroying the current context releases it without going through eglMakeCurrent
+    match context::current() {
+        Some(_) => CURRENT_SURFACE.with(|s| s.get()) as usize as EGLSurface,
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Pbuffers have no back buffer, so swapping one does not change its contents. It still counts
+/// as a frame for the frame sinks of the context rendering into it.
+#[no_mangle]
+pub extern "system" fn eglSwapBuffers(display: EGLDisplay, surface: EGLSurface) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    let surface = surface as usize as u32;
+    let exists = SURFACES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains_key(&surface);
+    if !exists {
+        return fail(EGL_BAD_SURFACE, EGL_FALSE);
+    }
+    if context::current().is_some() && CURRENT_SURFACE.with(|s| s.get()) == surface {
+        context::swap_current();
+    }
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglSwapInterval(display: EGLDisplay, interval: EGLint) -> EGLBoolean {
+    if !check_display(display) {
+        return EGL_FALSE;
+    }
+    if context::current().is_none() {
+        return fail(EGL_BAD_CONTEXT, EGL_FALSE);
+    }
+    // clamped to the config's range, as EGL requires
+    let interval = interval.clamp(0, 1) as u32;
+    context::with_current(|context| context.pacer.interval = interval);
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglWaitClient() -> EGLBoolean {
+    glFinish();
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglWaitGL() -> EGLBoolean {
+    eglWaitClient()
+}
+
+#[no_mangle]
+pub extern "system" fn eglReleaseThread() -> EGLBoolean {
+    context::make_current(0);
+    CURRENT_SURFACE.with(|s| s.set(0));
+    succeed(EGL_TRUE)
+}
+
+#[no_mangle]
+pub extern "system" fn eglGetProcAddress(name: *const c_char) -> *const c_void {
+    if name.is_null() {
+        return std::ptr::null();
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name) };
+    procs::lookup(name.to_bytes()).unwrap_or(std::ptr::null())
+}
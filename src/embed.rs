@@ -0,0 +1,464 @@
+//! A safe API for Rust programs that link minigl as a library instead of loading it as a GL
+//! driver.
+//!
+//! A `Context` renders offscreen into its own framebuffer. Each method makes the context current
+//! for the duration of the call and then restores whatever context was current before, so
+//! contexts can be used side by side, and alongside ones created through a window system API.
+
+use std::{cell::Cell, marker::PhantomData, mem};
+
+use crate::{
+    context::{self, Handle},
+    format::PixelFormat,
+    pixels::PixelStore,
+    rasterize::{DepthFunc, Framebuffer},
+    sink::{FrameSink, SinkId},
+    texture::TexEnvMode,
+    with_state, GLState, GLenum, MatrixMode, PrimitiveMode, GL_ADD, GL_ALPHA, GL_ALWAYS, GL_BGR,
+    GL_BGRA, GL_BLEND, GL_COLOR_BUFFER_BIT, GL_DECAL, GL_DEPTH_BUFFER_BIT, GL_DEPTH_TEST, GL_EQUAL,
+    GL_GEQUAL, GL_GREATER, GL_LEQUAL, GL_LESS, GL_LUMINANCE, GL_LUMINANCE_ALPHA, GL_MODELVIEW,
+    GL_MODULATE, GL_NEVER, GL_NOTEQUAL, GL_PROJECTION, GL_REPLACE, GL_RGB, GL_RGBA,
+    GL_SCISSOR_TEST, GL_STENCIL_BUFFER_BIT, GL_TEXTURE, GL_TEXTURE_2D, GL_TEXTURE_ENV,
+    GL_TEXTURE_ENV_MODE, GL_UNSIGNED_BYTE,
+};
+
+/// State that can be turned on and off with `Context::enable` and `Context::disable`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Capability {
+    DepthTest,
+    ScissorTest,
+    /// Texturing on the active texture unit.
+    Texture2D,
+}
+
+/// The layout of texels passed to `Context::tex_image_2d`, one byte per component.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Alpha,
+    Luminance,
+    LuminanceAlpha,
+    Rgb,
+    Rgba,
+    Bgr,
+    Bgra,
+}
+
+impl TextureFormat {
+    fn components(self) -> usize {
+        match self {
+            TextureFormat::Alpha | TextureFormat::Luminance => 1,
+            TextureFormat::LuminanceAlpha => 2,
+            TextureFormat::Rgb | TextureFormat::Bgr => 3,
+            TextureFormat::Rgba | TextureFormat::Bgra => 4,
+        }
+    }
+
+    /// The format argument to glTexImage2D, and the internal format it is stored as.
+    fn gl_formats(self) -> (GLenum, GLenum) {
+        match self {
+            TextureFormat::Alpha => (GL_ALPHA, GL_ALPHA),
+            TextureFormat::Luminance => (GL_LUMINANCE, GL_LUMINANCE),
+            TextureFormat::LuminanceAlpha => (GL_LUMINANCE_ALPHA, GL_LUMINANCE_ALPHA),
+            TextureFormat::Rgb => (GL_RGB, GL_RGB),
+            TextureFormat::Rgba => (GL_RGBA, GL_RGBA),
+            TextureFormat::Bgr => (GL_BGR, GL_RGB),
+            TextureFormat::Bgra => (GL_BGRA, GL_RGBA),
+        }
+    }
+}
+
+/// An offscreen rendering context.
+pub struct Context {
+    handle: Handle,
+    /// Two threads using one context at once would fight over making it current.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl Context {
+    /// Creates a context with a `width` x `height` framebuffer of the default pixel format.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::with_format(width, height, Default::default())
+    }
+
+    pub fn with_format(width: usize, height: usize, format: PixelFormat) -> Self {
+        let mut context = context::Context::new(GLState::new(width, height, format));
+        // nothing is displayed, so there is no refresh rate to pace swaps to
+        context.pacer.interval = 0;
+        Self {
+            handle: context::create(context),
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Creates a context that shares texture objects with this one.
+    pub fn new_shared(&self, width: usize, height: usize, format: PixelFormat) -> Self {
+        let shared = Self::with_format(width, height, format);
+        let namespace = self.with_inner(|context| context.state.namespace.clone());
+        shared.with_inner(|context| context.state.namespace = namespace);
+        shared
+    }
+
+    fn with_inner<R>(&self, f: impl FnOnce(&mut context::Context) -> R) -> R {
+        // the handle is only ever deleted by drop
+        context::with_context(self.handle, f).unwrap()
+    }
+
+    /// Runs `f` with this context current on the calling thread.
+    fn call<R>(&self, f: impl FnOnce() -> R) -> R {
+        let previous = context::current();
+        if previous == Some(self.handle) {
+            return f();
+        }
+        // this fails if the context is still current on another thread, either because a call
+        // panicked there before restoring the previous context, or because the handle was passed
+        // to a window system API, say from wglGetCurrentContext in a frame sink
+        assert!(
+            context::make_current(self.handle),
+            "minigl context is current on another thread"
+        );
+        let result = f();
+        context::make_current(previous.unwrap_or(0));
+        result
+    }
+
+    /// Runs `f` on the framebuffer. Color buffers hold BGRA pixels, bottom row first.
+    pub fn framebuffer<R>(&self, f: impl FnOnce(&Framebuffer) -> R) -> R {
+        self.with_inner(|context| f(&context.state.fb))
+    }
+
+    /// Reallocates the framebuffer at a new size, leaving the viewport alone like a window resize.
+    pub fn resize(&self, width: usize, height: usize) {
+        self.with_inner(|context| context.resize(width, height));
+    }
+
+    /// Swaps the front and back buffers of a double buffered context. Frame sinks are run with
+    /// the context current, so they can make GL calls on it.
+    pub fn swap_buffers(&self) {
+        self.call(|| context::swap_buffers(self.handle));
+    }
+
+    /// Has `sink` handed every frame this context swaps, after any sinks added earlier.
+    pub fn add_frame_sink(&self, sink: impl FrameSink + 'static) -> SinkId {
+        self.with_inner(|context| context.add_sink(Box::new(sink)))
+    }
+
+    /// Removes a sink added with `add_frame_sink`, returning false if there was no such sink.
+    pub fn remove_frame_sink(&self, id: SinkId) -> bool {
+        self.with_inner(|context| context.remove_sink(id))
+    }
+
+    pub fn finish(&self) {
+        self.call(|| crate::glFinish());
+    }
+
+    pub fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.call(|| crate::glViewport(x, y, width, height));
+    }
+
+    pub fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.call(|| crate::glScissor(x, y, width, height));
+    }
+
+    pub fn enable(&self, capability: Capability) {
+        self.call(|| crate::glEnable(capability.into()));
+    }
+
+    pub fn disable(&self, capability: Capability) {
+        self.call(|| crate::glDisable(capability.into()));
+    }
+
+    pub fn depth_func(&self, func: DepthFunc) {
+        let func = match func {
+            DepthFunc::Never => GL_NEVER,
+            DepthFunc::Less => GL_LESS,
+            DepthFunc::Equal => GL_EQUAL,
+            DepthFunc::LEqual => GL_LEQUAL,
+            DepthFunc::Greater => GL_GREATER,
+            DepthFunc::NotEqual => GL_NOTEQUAL,
+            DepthFunc::GEqual => GL_GEQUAL,
+            DepthFunc::Always => GL_ALWAYS,
+        };
+        self.call(|| crate::glDepthFunc(func));
+    }
+
+    pub fn depth_mask(&self, enabled: bool) {
+        self.call(|| crate::glDepthMask(enabled.into()));
+    }
+
+    pub fn color_mask(&self, red: bool, green: bool, blue: bool, alpha: bool) {
+        self.call(|| crate::glColorMask(red.into(), green.into(), blue.into(), alpha.into()));
+    }
+
+    pub fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.call(|| crate::glClearColor(red, green, blue, alpha));
+    }
+
+    pub fn clear_depth(&self, depth: f64) {
+        self.call(|| crate::glClearDepth(depth));
+    }
+
+    pub fn clear_stencil(&self, value: i32) {
+        self.call(|| crate::glClearStencil(value));
+    }
+
+    /// Clears the selected buffers to their clear values.
+    pub fn clear(&self, color: bool, depth: bool, stencil: bool) {
+        let mask = [
+            (color, GL_COLOR_BUFFER_BIT),
+            (depth, GL_DEPTH_BUFFER_BIT),
+            (stencil, GL_STENCIL_BUFFER_BIT),
+        ]
+        .into_iter()
+        .filter(|&(clear, _)| clear)
+        .fold(0, |mask, (_, bit)| mask | bit);
+        self.call(|| crate::glClear(mask));
+    }
+
+    pub fn matrix_mode(&self, mode: MatrixMode) {
+        let mode = match mode {
+            MatrixMode::ModelView => GL_MODELVIEW,
+            MatrixMode::Projection => GL_PROJECTION,
+            MatrixMode::Texture => GL_TEXTURE,
+        };
+        self.call(|| crate::glMatrixMode(mode));
+    }
+
+    pub fn load_identity(&self) {
+        self.call(|| crate::glLoadIdentity());
+    }
+
+    pub fn push_matrix(&self) {
+        self.call(|| crate::glPushMatrix());
+    }
+
+    pub fn pop_matrix(&self) {
+        self.call(|| crate::glPopMatrix());
+    }
+
+    pub fn ortho(&self, left: f64, right: f64, bottom: f64, top: f64, near: f64, far: f64) {
+        self.call(|| crate::glOrtho(left, right, bottom, top, near, far));
+    }
+
+    pub fn frustum(&self, left: f64, right: f64, bottom: f64, top: f64, near: f64, far: f64) {
+        self.call(|| crate::glFrustum(left, right, bottom, top, near, far));
+    }
+
+    pub fn rotate(&self, angle: f32, x: f32, y: f32, z: f32) {
+        self.call(|| crate::glRotatef(angle, x, y, z));
+    }
+
+    pub fn translate(&self, x: f32, y: f32, z: f32) {
+        self.call(|| crate::glTranslatef(x, y, z));
+    }
+
+    pub fn scale(&self, x: f32, y: f32, z: f32) {
+        self.call(|| crate::glScalef(x, y, z));
+    }
+
+    pub fn begin(&self, mode: PrimitiveMode) {
+        self.call(|| crate::glBegin(mode));
+    }
+
+    pub fn end(&self) {
+        self.call(|| crate::glEnd());
+    }
+
+    pub fn vertex2(&self, x: f32, y: f32) {
+        self.call(|| crate::glVertex2f(x, y));
+    }
+
+    pub fn vertex3(&self, x: f32, y: f32, z: f32) {
+        self.call(|| crate::glVertex3f(x, y, z));
+    }
+
+    pub fn vertex4(&self, x: f32, y: f32, z: f32, w: f32) {
+        self.call(|| crate::glVertex4f(x, y, z, w));
+    }
+
+    pub fn color3(&self, red: f32, green: f32, blue: f32) {
+        self.call(|| crate::glColor3f(red, green, blue));
+    }
+
+    pub fn color4(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.call(|| crate::glColor4f(red, green, blue, alpha));
+    }
+
+    /// Sets the texture coordinates of the first texture unit.
+    pub fn tex_coord2(&self, s: f32, t: f32) {
+        self.call(|| crate::glTexCoord2f(s, t));
+    }
+
+    /// Sets the texture coordinates of texture unit `unit`.
+    pub fn multi_tex_coord2(&self, unit: usize, s: f32, t: f32) {
+        self.call(|| crate::glMultiTexCoord2fARB(texture_unit(unit), s, t));
+    }
+
+    pub fn active_texture(&self, unit: usize) {
+        self.call(|| crate::glActiveTextureARB(texture_unit(unit)));
+    }
+
+    /// Returns an unused texture name.
+    pub fn gen_texture(&self) -> u32 {
+        let mut texture = 0;
+        self.call(|| crate::glGenTextures(1, &mut texture));
+        texture
+    }
+
+    pub fn delete_texture(&self, texture: u32) {
+        self.call(|| crate::glDeleteTextures(1, &texture));
+    }
+
+    /// Binds a texture to the active texture unit.
+    pub fn bind_texture(&self, texture: u32) {
+        self.call(|| crate::glBindTexture(GL_TEXTURE_2D, texture));
+    }
+
+    /// Replaces the image of the bound texture with `width` x `height` texels of tightly packed
+    /// rows, bottom row first.
+    ///
+    /// Panics if `texels` does not hold exactly that many texels.
+    pub fn tex_image_2d(&self, width: usize, height: usize, format: TextureFormat, texels: &[u8]) {
+        assert_eq!(texels.len(), width * height * format.components());
+        let (format, internal_format) = format.gl_formats();
+        self.call(|| {
+            let tight = PixelStore {
+                alignment: 1,
+                ..Default::default()
+            };
+            let unpack = with_state(|state| Some(mem::replace(&mut state.unpack, tight)));
+            crate::glTexImage2D(
+                GL_TEXTURE_2D,
+                0,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                format,
+                GL_UNSIGNED_BYTE,
+                texels.as_ptr().cast(),
+            );
+            with_state(|state| state.unpack = unpack.unwrap());
+        });
+    }
+
+    /// Sets the texture environment mode of the active texture unit.
+    pub fn tex_env_mode(&self, mode: TexEnvMode) {
+        let mode = match mode {
+            TexEnvMode::Modulate => GL_MODULATE,
+            TexEnvMode::Replace => GL_REPLACE,
+            TexEnvMode::Decal => GL_DECAL,
+            TexEnvMode::Blend => GL_BLEND,
+            TexEnvMode::Add => GL_ADD,
+        };
+        self.call(|| crate::glTexEnvi(GL_TEXTURE_ENV, GL_TEXTURE_ENV_MODE, mode as i32));
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        context::delete(self.handle);
+    }
+}
+
+impl From<Capability> for GLenum {
+    fn from(capability: Capability) -> Self {
+        match capability {
+            Capability::DepthTest => GL_DEPTH_TEST,
+            Capability::ScissorTest => GL_SCISSOR_TEST,
+            Capability::Texture2D => GL_TEXTURE_2D,
+        }
+    }
+}
+
+fn texture_unit(unit: usize) -> GLenum {
+    crate::GL_TEXTURE0_ARB + unit as GLenum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterize::ColorBuffer;
+
+    // stored as BGRA
+    const RED: [u8; 4] = [0, 0, 255, 255];
+    const BLUE: [u8; 4] = [255, 0, 0, 255];
+
+    /// Returns the BGRA pixels of the back buffer.
+    fn pixels(gl: &Context) -> Vec<[u8; 4]> {
+        gl.framebuffer(|fb| {
+            fb.color_buffer(ColorBuffer::Back)
+                .chunks_exact(4)
+                .map(|pixel| pixel.try_into().unwrap())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn incomplete_primitives_draw_nothing() {
+        let gl = Context::new(8, 8);
+        gl.clear_color(0.0, 0.0, 1.0, 1.0);
+        gl.clear(true, false, false);
+        gl.color3(1.0, 0.0, 0.0);
+        for mode in [
+            PrimitiveMode::Points,
+            PrimitiveMode::Lines,
+            PrimitiveMode::LineStrip,
+            PrimitiveMode::LineLoop,
+            PrimitiveMode::Triangles,
+            PrimitiveMode::TriangleStrip,
+            PrimitiveMode::TriangleFan,
+            PrimitiveMode::Quads,
+            PrimitiveMode::QuadStrip,
+            PrimitiveMode::Polygon,
+        ] {
+            for count in 0..3 {
+                gl.begin(mode);
+                for i in 0..count {
+                    gl.vertex2(if i == 1 { 0.9 } else { -0.9 }, -0.9);
+                }
+                gl.end();
+            }
+        }
+        assert!(pixels(&gl).iter().all(|&pixel| pixel == BLUE));
+
+        // popping the last matrix is ignored
+        gl.pop_matrix();
+        gl.begin(PrimitiveMode::Quads);
+        for (x, y) in [
+            (-0.9, -0.9),
+            (0.9, -0.9),
+            (0.9, 0.9),
+            (-0.9, 0.9),
+            (0.0, 0.0),
+        ] {
+            gl.vertex2(x, y);
+        }
+        gl.end();
+
+        assert_eq!(pixels(&gl)[4 * 8 + 4], RED);
+    }
+
+    #[test]
+    fn quad_strips_are_drawn() {
+        let gl = Context::new(8, 8);
+        gl.clear_color(0.0, 0.0, 1.0, 1.0);
+        gl.clear(true, false, false);
+        gl.color3(1.0, 0.0, 0.0);
+        gl.begin(PrimitiveMode::QuadStrip);
+        for (x, y) in [
+            (-0.9, -0.9),
+            (-0.9, 0.9),
+            (0.0, -0.9),
+            (0.0, 0.9),
+            (0.9, -0.9),
+        ] {
+            gl.vertex2(x, y);
+        }
+        gl.end();
+
+        // the left half is one quad, and the last vertex is left over
+        let pixels = pixels(&gl);
+        assert_eq!(pixels[4 * 8 + 2], RED);
+        assert_eq!(pixels[4 * 8 + 5], BLUE);
+    }
+}
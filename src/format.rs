@@ -0,0 +1,212 @@
+//! The framebuffer configurations minigl can render into.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelFormat {
+    /// Bits per pixel in the color buffer, including alpha. Colors are always stored as 8 bits
+    /// per channel.
+    pub color_bits: u8,
+    pub alpha_bits: u8,
+    pub depth_bits: u8,
+    pub stencil_bits: u8,
+    pub double_buffer: bool,
+    /// Whether swapping buffers copies the back buffer to the front one, leaving the back buffer
+    /// intact, rather than exchanging them. Always false for single buffered formats.
+    pub swap_copy: bool,
+}
+
+impl Default for PixelFormat {
+    fn default() -> Self {
+        Self {
+            color_bits: 32,
+            alpha_bits: 8,
+            depth_bits: 24,
+            stencil_bits: 8,
+            double_buffer: true,
+            swap_copy: false,
+        }
+    }
+}
+
+/// Every supported format, in order of preference. Window systems that number their formats
+/// count from 1 in this order.
+pub fn supported() -> impl Iterator<Item = PixelFormat> {
+    let buffering = [(true, false), (true, true), (false, false)];
+    buffering
+        .into_iter()
+        .flat_map(|(double_buffer, swap_copy)| {
+            [(32, 8), (24, 0)]
+                .into_iter()
+                .flat_map(move |(color_bits, alpha_bits)| {
+                    [24, 16, 32].into_iter().flat_map(move |depth_bits| {
+                        [8, 0].into_iter().map(move |stencil_bits| PixelFormat {
+                            color_bits,
+                            alpha_bits,
+                            depth_bits,
+                            stencil_bits,
+                            double_buffer,
+                            swap_copy,
+                        })
+                    })
+                })
+        })
+}
+
+/// What an application asked for when choosing a format. `None` means it does not care.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FormatRequest {
+    pub color_bits: u8,
+    pub alpha_bits: u8,
+    pub depth_bits: Option<u8>,
+    pub stencil_bits: u8,
+    pub double_buffer: Option<bool>,
+    pub swap_copy: Option<bool>,
+}
+
+/// Returns the index into `supported()` of the format closest to `request`.
+///
+/// Missing buffering mode, depth, stencil or alpha bits count against a format first, then
+/// differences in color depth, then bits that were not asked for, then the swap method.
+pub fn choose(request: &FormatRequest) -> usize {
+    let shortfall = |have: u8, want: u8| want.saturating_sub(have);
+    let excess = |have: u8, want: u8| have.saturating_sub(want);
+
+    supported()
+        .enumerate()
+        .min_by_key(|(_, format)| {
+            let depth = request.depth_bits.unwrap_or(format.depth_bits);
+            (
+                request
+                    .double_buffer
+                    .is_some_and(|double_buffer| double_buffer != format.double_buffer),
+                shortfall(format.depth_bits, depth),
+                shortfall(format.stencil_bits, request.stencil_bits),
+                shortfall(format.alpha_bits, request.alpha_bits),
+                match request.color_bits {
+                    0 => 0,
+                    bits => format.color_bits.abs_diff(bits),
+                },
+                excess(format.depth_bits, depth),
+                excess(format.stencil_bits, request.stencil_bits),
+                request
+                    .swap_copy
+                    .is_some_and(|swap_copy| swap_copy != format.swap_copy),
+            )
+        })
+        .map(|(index, _)| index)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chosen(request: FormatRequest) -> PixelFormat {
+        supported().nth(choose(&request)).unwrap()
+    }
+
+    #[test]
+    fn there_are_36_distinct_formats() {
+        let formats: Vec<_> = supported().collect();
+        assert_eq!(formats.len(), 36);
+        for (i, format) in formats.iter().enumerate() {
+            assert!(!formats[..i].contains(format));
+            assert!(format.double_buffer || !format.swap_copy);
+        }
+    }
+
+    #[test]
+    fn every_format_can_be_chosen_exactly() {
+        for (index, format) in supported().enumerate() {
+            let request = FormatRequest {
+                color_bits: format.color_bits,
+                alpha_bits: format.alpha_bits,
+                depth_bits: Some(format.depth_bits),
+                stencil_bits: format.stencil_bits,
+                double_buffer: Some(format.double_buffer),
+                swap_copy: format.double_buffer.then_some(format.swap_copy),
+            };
+            assert_eq!(choose(&request), index, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn ties_go_to_the_most_preferred_format() {
+        // a request for no stencil bits rules out the first format, which has them
+        assert_eq!(choose(&FormatRequest::default()), 1);
+        let stencil = FormatRequest {
+            stencil_bits: 8,
+            ..Default::default()
+        };
+        assert_eq!(choose(&stencil), 0);
+        let single = FormatRequest {
+            stencil_bits: 8,
+            double_buffer: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(choose(&single), 24);
+        assert_eq!(
+            chosen(single),
+            PixelFormat {
+                double_buffer: false,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn missing_bits_outweigh_extra_ones() {
+        let format = chosen(FormatRequest {
+            depth_bits: Some(0),
+            ..Default::default()
+        });
+        assert_eq!((format.depth_bits, format.stencil_bits), (16, 0));
+
+        let format = chosen(FormatRequest {
+            depth_bits: Some(48),
+            stencil_bits: 16,
+            ..Default::default()
+        });
+        assert_eq!((format.depth_bits, format.stencil_bits), (32, 8));
+
+        let format = chosen(FormatRequest {
+            depth_bits: Some(20),
+            ..Default::default()
+        });
+        assert_eq!(format.depth_bits, 24);
+    }
+
+    #[test]
+    fn alpha_outweighs_color_depth() {
+        let format = chosen(FormatRequest {
+            color_bits: 24,
+            ..Default::default()
+        });
+        assert_eq!((format.color_bits, format.alpha_bits), (24, 0));
+
+        let format = chosen(FormatRequest {
+            color_bits: 24,
+            alpha_bits: 8,
+            ..Default::default()
+        });
+        assert_eq!((format.color_bits, format.alpha_bits), (32, 8));
+    }
+
+    #[test]
+    fn buffering_outweighs_everything_else() {
+        let format = chosen(FormatRequest {
+            depth_bits: Some(32),
+            stencil_bits: 8,
+            double_buffer: Some(false),
+            swap_copy: Some(true),
+            ..Default::default()
+        });
+        assert!(!format.double_buffer);
+        assert_eq!(format.depth_bits, 32);
+
+        let format = chosen(FormatRequest {
+            swap_copy: Some(true),
+            ..Default::default()
+        });
+        assert!(format.double_buffer && format.swap_copy);
+    }
+}
@@ -0,0 +1,46 @@
+//! Gamma ramp emulation.
+//!
+//! Games adjust brightness by changing the display's gamma ramp, which would affect the whole
+//! desktop and does nothing for a software renderer's blits anyway. Instead the ramp is kept here
+//! and applied to the color buffer as it is presented.
+
+use std::{borrow::Cow, sync::Mutex};
+
+/// 256 entries for each of red, green and blue, in the same layout as SetDeviceGammaRamp's.
+pub type GammaRamp = [[u16; 256]; 3];
+
+/// The current ramp reduced to 8 bits, or `None` if it is the identity.
+static LOOKUP: Mutex<Option<[[u8; 256]; 3]>> = Mutex::new(None);
+
+fn identity() -> GammaRamp {
+    [std::array::from_fn(|i| (i as u16) << 8 | i as u16); 3]
+}
+
+pub fn set(ramp: &GammaRamp) {
+    let lookup = ramp.map(|channel| channel.map(|value| (value >> 8) as u8));
+    let is_identity = lookup
+        .iter()
+        .all(|channel| channel.iter().enumerate().all(|(i, &v)| v as usize == i));
+    *LOOKUP.lock().unwrap_or_else(|e| e.into_inner()) = (!is_identity).then_some(lookup);
+}
+
+pub fn get() -> GammaRamp {
+    match *LOOKUP.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(lookup) => lookup.map(|channel| channel.map(|v| (v as u16) << 8 | v as u16)),
+        None => identity(),
+    }
+}
+
+/// Applies the ramp to a BGRA color buffer, borrowing it unchanged if the ramp is the identity.
+pub fn apply(buffer: &[u8]) -> Cow<'_, [u8]> {
+    let Some([red, green, blue]) = *LOOKUP.lock().unwrap_or_else(|e| e.into_inner()) else {
+        return Cow::Borrowed(buffer);
+    };
+    let mut corrected = buffer.to_vec();
+    for pixel in corrected.chunks_exact_mut(4) {
+        pixel[0] = blue[pixel[0] as usize];
+        pixel[1] = green[pixel[1] as usize];
+        pixel[2] = red[pixel[2] as usize];
+    }
+    Cow::Owned(corrected)
+}
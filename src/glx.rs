@@ -0,0 +1,720 @@
+//! The GLX front end, which presents to X11 windows with MIT-SHM where the server supports it
+//! and XPutImage otherwise.
+
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    ffi::{c_char, c_int, c_uint, c_ulong, c_void},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use crate::{
+    context::{self, Context, Handle},
+    format::{self, FormatRequest, PixelFormat},
+    glFinish,
+    presenter::Presenter,
+    procs, x11, GLState, GLubyte,
+};
+
+type GLXContext = *mut c_void;
+type GLXDrawable = x11::XID;
+
+const GLX_USE_GL: c_int = 1;
+const GLX_BUFFER_SIZE: c_int = 2;
+const GLX_LEVEL: c_int = 3;
+const GLX_RGBA: c_int = 4;
+const GLX_DOUBLEBUFFER: c_int = 5;
+const GLX_STEREO: c_int = 6;
+const GLX_AUX_BUFFERS: c_int = 7;
+const GLX_RED_SIZE: c_int = 8;
+const GLX_GREEN_SIZE: c_int = 9;
+const GLX_BLUE_SIZE: c_int = 10;
+const GLX_ALPHA_SIZE: c_int = 11;
+const GLX_DEPTH_SIZE: c_int = 12;
+const GLX_STENCIL_SIZE: c_int = 13;
+const GLX_ACCUM_RED_SIZE: c_int = 14;
+const GLX_ACCUM_GREEN_SIZE: c_int = 15;
+const GLX_ACCUM_BLUE_SIZE: c_int = 16;
+const GLX_ACCUM_ALPHA_SIZE: c_int = 17;
+
+const GLX_BAD_ATTRIBUTE: c_int = 2;
+const GLX_BAD_VISUAL: c_int = 4;
+const GLX_BAD_VALUE: c_int = 6;
+
+const GLX_VENDOR: c_int = 1;
+const GLX_VERSION: c_int = 2;
+const GLX_EXTENSIONS: c_int = 3;
+
+/// The pixel formats chosen by glXChooseVisual. Every format is rendered through the same few X
+/// visuals, so each is kept by the address of the XVisualInfo it was returned in, to keep apart
+/// visuals the application probed for different formats. Applications that copy the XVisualInfo
+/// get the format last chosen for its visual ID instead.
+struct VisualFormats {
+    by_address: BTreeMap<usize, (x11::VisualID, PixelFormat)>,
+    by_id: BTreeMap<x11::VisualID, PixelFormat>,
+}
+
+static VISUAL_FORMATS: Mutex<VisualFormats> = Mutex::new(VisualFormats {
+    by_address: BTreeMap::new(),
+    by_id: BTreeMap::new(),
+});
+
+fn visual_formats() -> MutexGuard<'static, VisualFormats> {
+    VISUAL_FORMATS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records the format a visual returned by glXChooseVisual was chosen for.
+fn remember_format(visual: *const x11::XVisualInfo, format: PixelFormat) {
+    let visualid = unsafe { (*visual).visualid };
+    let mut formats = visual_formats();
+    formats
+        .by_address
+        .insert(visual as usize, (visualid, format));
+    formats.by_id.insert(visualid, format);
+}
+
+/// Returns the format of a visual, or the default one for visuals minigl did not choose.
+fn visual_format(visual: *const x11::XVisualInfo) -> PixelFormat {
+    let visualid = unsafe { (*visual).visualid };
+    let formats = visual_formats();
+    // an address can be reused once the application frees the XVisualInfo
+    match formats.by_address.get(&(visual as usize)) {
+        Some(&(id, format)) if id == visualid => format,
+        _ => formats.by_id.get(&visualid).copied().unwrap_or_default(),
+    }
+}
+
+/// Turns a glXChooseVisual attribute list into a format request, or `None` if it asks for
+/// something minigl never provides.
+fn parse_attributes(mut attributes: *const c_int) -> Option<FormatRequest> {
+    // without GLX_DOUBLEBUFFER, only single buffered visuals are considered
+    let mut request = FormatRequest {
+        double_buffer: Some(false),
+        ..Default::default()
+    };
+    let mut rgba = false;
+
+    loop {
+        let attribute = unsafe { *attributes };
+        attributes = unsafe { attributes.add(1) };
+        match attribute {
+            0 => break,
+            GLX_USE_GL => {}
+            GLX_RGBA => rgba = true,
+            GLX_DOUBLEBUFFER => request.double_buffer = Some(true),
+            GLX_STEREO => return None,
+            _ => {
+                let value = unsafe { *attributes };
+                attributes = unsafe { attributes.add(1) };
+                let bits = u8::try_from(value).ok()?;
+                match attribute {
+                    // only meaningful for color index visuals
+                    GLX_BUFFER_SIZE => {}
+                    GLX_LEVEL | GLX_AUX_BUFFERS if value != 0 => return None,
+                    GLX_LEVEL | GLX_AUX_BUFFERS => {}
+                    GLX_RED_SIZE | GLX_GREEN_SIZE | GLX_BLUE_SIZE if bits > 8 => return None,
+                    GLX_RED_SIZE | GLX_GREEN_SIZE | GLX_BLUE_SIZE => {}
+                    GLX_ALPHA_SIZE => request.alpha_bits = bits,
+                    GLX_DEPTH_SIZE => request.depth_bits = Some(bits),
+                    GLX_STENCIL_SIZE => request.stencil_bits = bits,
+                    GLX_ACCUM_RED_SIZE | GLX_ACCUM_GREEN_SIZE | GLX_ACCUM_BLUE_SIZE
+                    | GLX_ACCUM_ALPHA_SIZE
+                        if value != 0 =>
+                    {
+                        return None
+                    }
+                    GLX_ACCUM_RED_SIZE | GLX_ACCUM_GREEN_SIZE | GLX_ACCUM_BLUE_SIZE
+                    | GLX_ACCUM_ALPHA_SIZE => {}
+                    _ => return None,
+                }
+            }
+        }
+    }
+
+    rgba.then_some(request)
+}
+
+#[no_mangle]
+pub extern "C" fn glXChooseVisual(
+    display: *mut x11::Display,
+    screen: c_int,
+    attributes: *const c_int,
+) -> *mut x11::XVisualInfo {
+    if attributes.is_null() {
+        return std::ptr::null_mut();
+    }
+    let Some(request) = parse_attributes(attributes) else {
+        return std::ptr::null_mut();
+    };
+    let format = format::supported().nth(format::choose(&request)).unwrap();
+    // glXChooseVisual only returns visuals with at least the requested sizes
+    if format.alpha_bits < request.alpha_bits
+        || format.depth_bits < request.depth_bits.unwrap_or(0)
+        || format.stencil_bits < request.stencil_bits
+    {
+        return std::ptr::null_mut();
+    }
+
+    for depth in [24, 32] {
+        let template = x11::XVisualInfo {
+            visual: std::ptr::null_mut(),
+            visualid: 0,
+            screen,
+            depth,
+            class: x11::TRUE_COLOR,
+            red_mask: 0,
+            green_mask: 0,
+            blue_mask: 0,
+            colormap_size: 0,
+            bits_per_rgb: 0,
+        };
+        let mut count = 0;
+        let visual = unsafe {
+            x11::XGetVisualInfo(
+                display,
+                x11::VISUAL_SCREEN_MASK | x11::VISUAL_DEPTH_MASK | x11::VISUAL_CLASS_MASK,
+                &template,
+                &mut count,
+            )
+        };
+        if !visual.is_null() && count > 0 {
+            remember_format(visual, format);
+            return visual;
+        }
+    }
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetConfig(
+    _display: *mut x11::Display,
+    visual: *const x11::XVisualInfo,
+    attribute: c_int,
+    value: *mut c_int,
+) -> c_int {
+    if visual.is_null() {
+        return GLX_BAD_VISUAL;
+    }
+    if value.is_null() {
+        return GLX_BAD_VALUE;
+    }
+    let format = visual_format(visual);
+    let result = match attribute {
+        GLX_USE_GL | GLX_RGBA => 1,
+        GLX_BUFFER_SIZE => format.color_bits as c_int,
+        GLX_DOUBLEBUFFER => format.double_buffer as c_int,
+        GLX_RED_SIZE | GLX_GREEN_SIZE | GLX_BLUE_SIZE => 8,
+        GLX_ALPHA_SIZE => format.alpha_bits as c_int,
+        GLX_DEPTH_SIZE => format.depth_bits as c_int,
+        GLX_STENCIL_SIZE => format.stencil_bits as c_int,
+        GLX_LEVEL | GLX_STEREO | GLX_AUX_BUFFERS => 0,
+        GLX_ACCUM_RED_SIZE | GLX_ACCUM_GREEN_SIZE | GLX_ACCUM_BLUE_SIZE | GLX_ACCUM_ALPHA_SIZE => 0,
+        _ => return GLX_BAD_ATTRIBUTE,
+    };
+    unsafe { *value = result };
+    0
+}
+
+fn handle(context: GLXContext) -> Handle {
+    context as usize as Handle
+}
+
+#[no_mangle]
+pub extern "C" fn glXCreateContext(
+    _display: *mut x11::Display,
+    visual: *const x11::XVisualInfo,
+    share_list: GLXContext,
+    _direct: x11::Bool,
+) -> GLXContext {
+    if visual.is_null() {
+        return std::ptr::null_mut();
+    }
+    let mut state = GLState::new(0, 0, visual_format(visual));
+    if !share_list.is_null() {
+        match context::with_context(handle(share_list), |context| {
+            context.state.namespace.clone()
+        }) {
+            Some(namespace) => state.namespace = namespace,
+            None => return std::ptr::null_mut(),
+        }
+    }
+    context::create(Context::new(state)) as usize as GLXContext
+}
+
+#[no_mangle]
+pub extern "C" fn glXDestroyContext(_display: *mut x11::Display, context: GLXContext) {
+    context::delete(handle(context));
+}
+
+/// An XImage to copy frames into before sending them to the server, in shared memory if
+/// possible.
+struct Image {
+    image: *mut x11::XImage,
+    shm: Option<Box<x11::XShmSegmentInfo>>,
+    /// Backs the image when it is not in shared memory.
+    buffer: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+/// Presents to an X11 window.
+struct WindowPresenter {
+    display: *mut x11::Display,
+    window: x11::XID,
+    gc: x11::GC,
+    visual: *mut x11::Visual,
+    depth: c_uint,
+    image: Option<Image>,
+}
+
+// Xlib handles are only used from whichever thread the context is current on, and making sure
+// Xlib is safe to use from that thread is up to the application
+unsafe impl Send for WindowPresenter {}
+
+static SHM_ATTACH_FAILED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn shm_error_handler(
+    _display: *mut x11::Display,
+    _event: *mut x11::XErrorEvent,
+) -> c_int {
+    SHM_ATTACH_FAILED.store(true, Ordering::Relaxed);
+    0
+}
+
+impl WindowPresenter {
+    fn new(display: *mut x11::Display, window: x11::XID) -> Self {
+        let mut attributes: x11::XWindowAttributes = unsafe { std::mem::zeroed() };
+        unsafe { x11::XGetWindowAttributes(display, window, &mut attributes) };
+        Self {
+            display,
+            window,
+            gc: unsafe { x11::XCreateGC(display, window, 0, std::ptr::null_mut()) },
+            visual: attributes.visual,
+            depth: attributes.depth as c_uint,
+            image: None,
+        }
+    }
+
+    /// Creates an image in shared memory, or returns `None` if the server cannot use it.
+    fn create_shm_image(&self, width: usize, height: usize) -> Option<Image> {
+        if unsafe { x11::XShmQueryExtension(self.display) } == x11::FALSE {
+            return None;
+        }
+
+        let mut shm = Box::new(x11::XShmSegmentInfo {
+            shmseg: 0,
+            shmid: -1,
+            shmaddr: std::ptr::null_mut(),
+            read_only: x11::FALSE,
+        });
+        let image = unsafe {
+            x11::XShmCreateImage(
+                self.display,
+                self.visual,
+                self.depth,
+                x11::Z_PIXMAP,
+                std::ptr::null_mut(),
+                &mut *shm,
+                width as c_uint,
+                height as c_uint,
+            )
+        };
+        if image.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let size = (*image).bytes_per_line as usize * height;
+            shm.shmid = x11::shmget(x11::IPC_PRIVATE, size, x11::IPC_CREAT | 0o600);
+            if shm.shmid < 0 {
+                x11::XDestroyImage(image);
+                return None;
+            }
+            let address = x11::shmat(shm.shmid, std::ptr::null(), 0);
+            // the segment goes away once everything has detached from it
+            x11::shmctl(shm.shmid, x11::IPC_RMID, std::ptr::null_mut());
+            if address as isize == -1 {
+                x11::XDestroyImage(image);
+                return None;
+            }
+            shm.shmaddr = address as *mut c_char;
+            (*image).data = shm.shmaddr;
+
+            // attaching fails asynchronously for remote displays, so catch the error
+            SHM_ATTACH_FAILED.store(false, Ordering::Relaxed);
+            let previous = x11::XSetErrorHandler(Some(shm_error_handler));
+            x11::XShmAttach(self.display, &mut *shm);
+            x11::XSync(self.display, x11::FALSE);
+            x11::XSetErrorHandler(previous);
+            if SHM_ATTACH_FAILED.load(Ordering::Relaxed) {
+                (*image).data = std::ptr::null_mut();
+                x11::XDestroyImage(image);
+                x11::shmdt(address);
+                return None;
+            }
+        }
+
+        Some(Image {
+            image,
+            shm: Some(shm),
+            buffer: Vec::new(),
+            width,
+            height,
+        })
+    }
+
+    fn create_image(&self, width: usize, height: usize) -> Option<Image> {
+        if let Some(image) = self.create_shm_image(width, height) {
+            return Some(image);
+        }
+
+        let image = unsafe {
+            x11::XCreateImage(
+                self.display,
+                self.visual,
+                self.depth,
+                x11::Z_PIXMAP,
+                0,
+                std::ptr::null_mut(),
+                width as c_uint,
+                height as c_uint,
+                32,
+                0,
+            )
+        };
+        if image.is_null() {
+            return None;
+        }
+        let mut buffer = vec![0; unsafe { (*image).bytes_per_line } as usize * height];
+        unsafe { (*image).data = buffer.as_mut_ptr() as *mut c_char };
+        Some(Image {
+            image,
+            shm: None,
+            buffer,
+            width,
+            height,
+        })
+    }
+
+    fn destroy_image(&mut self) {
+        let Some(mut image) = self.image.take() else {
+            return;
+        };
+        unsafe {
+            if let Some(shm) = &mut image.shm {
+                x11::XShmDetach(self.display, &mut **shm);
+                x11::XSync(self.display, x11::FALSE);
+                x11::shmdt(shm.shmaddr as *const c_void);
+            }
+            // the data is not Xlib's to free
+            (*image.image).data = std::ptr::null_mut();
+            x11::XDestroyImage(image.image);
+        }
+        drop(image.buffer);
+    }
+}
+
+impl Drop for WindowPresenter {
+    fn drop(&mut self) {
+        self.destroy_image();
+        unsafe { x11::XFreeGC(self.display, self.gc) };
+    }
+}
+
+/// Scales an 8 bit channel to the bits selected by `mask`.
+fn channel(value: u8, mask: c_ulong) -> c_ulong {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones().min(8);
+    (value as c_ulong >> (8 - bits)) << shift
+}
+
+impl Presenter for WindowPresenter {
+    fn size(&self) -> Option<(usize, usize)> {
+        let mut attributes: x11::XWindowAttributes = unsafe { std::mem::zeroed() };
+        if unsafe { x11::XGetWindowAttributes(self.display, self.window, &mut attributes) } == 0 {
+            return None;
+        }
+        Some((attributes.width as usize, attributes.height as usize))
+    }
+
+    fn present(&mut self, pixels: &[u8], width: usize, height: usize) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        if self
+            .image
+            .as_ref()
+            .is_none_or(|image| (image.width, image.height) != (width, height))
+        {
+            self.destroy_image();
+            self.image = self.create_image(width, height);
+        }
+        let Some(image) = &self.image else {
+            return;
+        };
+
+        let ximage = unsafe { &*image.image };
+        let stride = ximage.bytes_per_line as usize;
+        let data =
+            unsafe { std::slice::from_raw_parts_mut(ximage.data as *mut u8, stride * height) };
+        let bytes_per_pixel = ximage.bits_per_pixel as usize / 8;
+        let is_bgrx = ximage.bits_per_pixel == 32
+            && ximage.byte_order == x11::LSB_FIRST
+            && (ximage.red_mask, ximage.green_mask, ximage.blue_mask) == (0xff0000, 0xff00, 0xff);
+
+        for y in 0..height {
+            // X images have their first row at the top
+            let src = &pixels[y * width * 4..(y + 1) * width * 4];
+            let dst = &mut data[(height - 1 - y) * stride..][..width * bytes_per_pixel];
+            if is_bgrx {
+                dst.copy_from_slice(src);
+                continue;
+            }
+            for (bgra, dst) in src
+                .chunks_exact(4)
+                .zip(dst.chunks_exact_mut(bytes_per_pixel))
+            {
+                let pixel = channel(bgra[2], ximage.red_mask)
+                    | channel(bgra[1], ximage.green_mask)
+                    | channel(bgra[0], ximage.blue_mask);
+                let bytes = (pixel as u32).to_le_bytes();
+                if ximage.byte_order == x11::LSB_FIRST {
+                    dst.copy_from_slice(&bytes[..bytes_per_pixel]);
+                } else {
+                    for (i, byte) in dst.iter_mut().enumerate() {
+                        *byte = bytes[bytes_per_pixel - 1 - i];
+                    }
+                }
+            }
+        }
+
+        unsafe {
+            if image.shm.is_some() {
+                x11::XShmPutImage(
+                    self.display,
+                    self.window,
+                    self.gc,
+                    image.image,
+                    0,
+                    0,
+                    0,
+                    0,
+                    width as c_uint,
+                    height as c_uint,
+                    x11::FALSE,
+                );
+                // the next frame must not be written until the server is done reading this one
+                x11::XSync(self.display, x11::FALSE);
+            } else {
+                x11::XPutImage(
+                    self.display,
+                    self.window,
+                    self.gc,
+                    image.image,
+                    0,
+                    0,
+                    0,
+                    0,
+                    width as c_uint,
+                    height as c_uint,
+                );
+                x11::XFlush(self.display);
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// The display and drawable passed to the last successful glXMakeCurrent on this thread.
+    static CURRENT: Cell<(*mut x11::Display, GLXDrawable)> =
+        const { Cell::new((std::ptr::null_mut(), 0)) };
+}
+
+#[no_mangle]
+pub extern "C" fn glXMakeCurrent(
+    display: *mut x11::Display,
+    drawable: GLXDrawable,
+    context: GLXContext,
+) -> x11::Bool {
+    let handle = handle(context);
+    // some applications make their context current every frame, so avoid setting up the window
+    // again if nothing changed
+    let unchanged = context::current() == Some(handle)
+        && CURRENT.with(|current| current.get()) == (display, drawable);
+
+    if !context::make_current(handle) {
+        return x11::FALSE;
+    }
+    if handle != 0 && !unchanged {
+        let presenter = WindowPresenter::new(display, drawable);
+        context::with_current(|context| context.set_presenter(Box::new(presenter)));
+    }
+    CURRENT.with(|current| current.set((display, drawable)));
+    x11::TRUE
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetCurrentContext() -> GLXContext {
+    context::current().unwrap_or(0) as usize as GLXContext
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetCurrentDrawable() -> GLXDrawable {
+    // destroying the current context releases it without going through glXMakeCurrent
+    match context::current() {
+        Some(_) => CURRENT.with(|current| current.get().1),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetCurrentDisplay() -> *mut x11::Display {
+    match context::current() {
+        Some(_) => CURRENT.with(|current| current.get().0),
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn glXSwapBuffers(_display: *mut x11::Display, _drawable: GLXDrawable) {
+    // the drawable has to be the one the current context is bound to, which is where the
+    // context presents anyway
+    context::swap_current();
+}
+
+#[no_mangle]
+pub extern "C" fn glXIsDirect(_display: *mut x11::Display, _context: GLXContext) -> x11::Bool {
+    x11::TRUE
+}
+
+#[no_mangle]
+pub extern "C" fn glXQueryExtension(
+    _display: *mut x11::Display,
+    error_base: *mut c_int,
+    event_base: *mut c_int,
+) -> x11::Bool {
+    // there is no server side extension, so no errors or events
+    for base in [error_base, event_base] {
+        if let Some(base) = unsafe { base.as_mut() } {
+            *base = 0;
+        }
+    }
+    x11::TRUE
+}
+
+#[no_mangle]
+pub extern "C" fn glXQueryVersion(
+    _display: *mut x11::Display,
+    major: *mut c_int,
+    minor: *mut c_int,
+) -> x11::Bool {
+    for (version, value) in [(major, 1), (minor, 2)] {
+        if let Some(version) = unsafe { version.as_mut() } {
+            *version = value;
+        }
+    }
+    x11::TRUE
+}
+
+fn glx_string(name: c_int) -> *const c_char {
+    match name {
+        GLX_VENDOR => c"minigl".as_ptr(),
+        GLX_VERSION => c"1.2 minigl".as_ptr(),
+        GLX_EXTENSIONS => c"".as_ptr(),
+        _ => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetClientString(_display: *mut x11::Display, name: c_int) -> *const c_char {
+    glx_string(name)
+}
+
+#[no_mangle]
+pub extern "C" fn glXQueryServerString(
+    _display: *mut x11::Display,
+    _screen: c_int,
+    name: c_int,
+) -> *const c_char {
+    glx_string(name)
+}
+
+#[no_mangle]
+pub extern "C" fn glXQueryExtensionsString(
+    _display: *mut x11::Display,
+    _screen: c_int,
+) -> *const c_char {
+    glx_string(GLX_EXTENSIONS)
+}
+
+#[no_mangle]
+pub extern "C" fn glXWaitGL() {
+    glFinish();
+}
+
+#[no_mangle]
+pub extern "C" fn glXWaitX() {
+    let display = glXGetCurrentDisplay();
+    if !display.is_null() {
+        unsafe { x11::XSync(display, x11::FALSE) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetProcAddressARB(name: *const GLubyte) -> *const c_void {
+    if name.is_null() {
+        return std::ptr::null();
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name as *const c_char) };
+    procs::lookup(name.to_bytes()).unwrap_or(std::ptr::null())
+}
+
+#[no_mangle]
+pub extern "C" fn glXGetProcAddress(name: *const GLubyte) -> *const c_void {
+    glXGetProcAddressARB(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visual_info(visualid: x11::VisualID) -> x11::XVisualInfo {
+        x11::XVisualInfo {
+            visual: std::ptr::null_mut(),
+            visualid,
+            screen: 0,
+            depth: 24,
+            class: x11::TRUE_COLOR,
+            red_mask: 0,
+            green_mask: 0,
+            blue_mask: 0,
+            colormap_size: 0,
+            bits_per_rgb: 0,
+        }
+    }
+
+    #[test]
+    fn visuals_keep_the_format_they_were_chosen_for() {
+        let double = PixelFormat::default();
+        let single = PixelFormat {
+            double_buffer: false,
+            ..Default::default()
+        };
+        let (first, second) = (visual_info(0x4242), visual_info(0x4242));
+        remember_format(&first, double);
+        remember_format(&second, single);
+        assert_eq!(visual_format(&first), double);
+        assert_eq!(visual_format(&second), single);
+
+        // copies only have the visual ID to go by
+        let copy = visual_info(0x4242);
+        assert_eq!(visual_format(&copy), single);
+        assert_eq!(visual_format(&visual_info(0x4343)), PixelFormat::default());
+    }
+}
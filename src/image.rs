@@ -0,0 +1,155 @@
+//! Encoders for the image files minigl writes when asked to save frames.
+//!
+//! PNGs are written with uncompressed deflate blocks. They are bigger than they need to be, but
+//! any reader accepts them and there is no compressor to depend on.
+
+use std::io::{self, Write};
+
+/// An 8-bit image with the top row first, as image files expect.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// 1 for grayscale, 3 for RGB.
+    pub channels: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Converts BGRA pixels listed bottom row first, `stride` bytes apart, to RGB.
+    pub fn from_bgra(pixels: &[u8], width: usize, height: usize, stride: usize) -> Self {
+        let mut data = Vec::with_capacity(width * height * 3);
+        for y in (0..height).rev() {
+            for bgra in pixels[y * stride..][..width * 4].chunks_exact(4) {
+                data.extend_from_slice(&[bgra[2], bgra[1], bgra[0]]);
+            }
+        }
+        Self {
+            width,
+            height,
+            channels: 3,
+            data,
+        }
+    }
+
+    /// Converts depth values listed bottom row first to grayscale, with the far plane white.
+    pub fn from_depth(depth: &[f32], width: usize, height: usize) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in (0..height).rev() {
+            let row = &depth[y * width..][..width];
+            data.extend(
+                row.iter()
+                    .map(|&z| (z.clamp(0.0, 1.0) * 255.0).round() as u8),
+            );
+        }
+        Self {
+            width,
+            height,
+            channels: 1,
+            data,
+        }
+    }
+
+    /// Writes a binary PPM, or a PGM if the image is grayscale.
+    pub fn write_pnm(&self, out: &mut impl Write) -> io::Result<()> {
+        let magic = if self.channels == 1 { "P5" } else { "P6" };
+        write!(out, "{magic}\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.data)
+    }
+
+    pub fn write_png(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let color_type = if self.channels == 1 { 0 } else { 2 };
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per sample, deflate, adaptive filtering, no interlacing
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        write_chunk(out, b"IHDR", &header)?;
+
+        // every row gets filter type 0, which leaves it as it is
+        let row_size = self.width * self.channels;
+        let mut filtered = Vec::with_capacity((row_size + 1) * self.height);
+        for row in self.data.chunks_exact(row_size.max(1)).take(self.height) {
+            filtered.push(0);
+            filtered.extend_from_slice(row);
+        }
+        write_chunk(out, b"IDAT", &zlib_stored(&filtered))?;
+
+        write_chunk(out, b"IEND", &[])
+    }
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(crc32(!0, kind), data);
+    out.write_all(&(!crc).to_be_bytes())
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+
+    let blocks = data.len().div_ceil(MAX_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // deflate with a 32K window and no preset dictionary, with the check bits the header needs
+    stream.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(MAX_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(chunk);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+/// Continues a CRC-32 over `data`. Start with `!0` and invert the result.
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                c = if c & 1 != 0 {
+                    0xedb88320 ^ c >> 1
+                } else {
+                    c >> 1
+                };
+                bit += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ crc >> 8;
+    }
+    crc
+}
@@ -1,11 +1,56 @@
+// entry points are called from C and take whatever pointers the caller hands them
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+mod capture;
+mod context;
+mod dump;
+#[cfg(feature = "egl")]
+mod egl;
+mod embed;
+mod format;
+mod gamma;
+#[cfg(feature = "glx")]
+mod glx;
+pub mod image;
 mod math;
+mod namespace;
+mod osmesa;
+mod pacing;
+mod pixels;
+mod presenter;
+mod procs;
 mod rasterize;
+pub mod replay;
+mod sink;
+mod texture;
+#[macro_use]
+pub mod trace;
+#[cfg(windows)]
+mod wgl;
+#[cfg(windows)]
 mod win32;
+#[cfg(feature = "glx")]
+mod x11;
 
-use std::{cell::RefCell, ffi::c_void};
+use std::ffi::c_void;
+
+pub use dump::parse_range;
+pub use embed::{Capability, Context, TextureFormat};
+pub use format::PixelFormat;
+pub use rasterize::{ColorBuffer, DepthFunc, Framebuffer};
+pub use sink::{Frame, FrameFormat, FrameSink, SinkId};
+pub use texture::TexEnvMode;
 
 use math::{Mat4, Vec4};
-use rasterize::Framebuffer;
+use namespace::SharedNamespace;
+use pixels::{BitmapLayout, Layout, PixelStore};
+use rasterize::{DrawBuffer, FragmentOps, Rect};
+use texture::{BaseFormat, TextureUnit, MAX_TEXTURE_UNITS};
+// procs looks extension entry points up by their path from the crate root
+#[cfg(windows)]
+use wgl::{
+    wglGetDeviceGammaRamp3DFX, wglGetSwapIntervalEXT, wglSetDeviceGammaRamp3DFX, wglSwapIntervalEXT,
+};
 
 type GLenum = std::ffi::c_uint;
 type GLboolean = std::ffi::c_uchar;
@@ -13,20 +58,105 @@ type GLbitfield = std::ffi::c_uint;
 type GLint = std::ffi::c_int;
 type GLsizei = std::ffi::c_int;
 type GLubyte = std::ffi::c_uchar;
+type GLushort = std::ffi::c_ushort;
 type GLuint = std::ffi::c_uint;
 type GLfloat = std::ffi::c_float;
 type GLclampf = std::ffi::c_float;
 type GLdouble = std::ffi::c_double;
+type GLclampd = std::ffi::c_double;
 type GLvoid = std::ffi::c_void;
 
+const GL_FALSE: GLboolean = 0;
+
+const GL_NONE: GLenum = 0;
+const GL_DEPTH_BUFFER_BIT: GLbitfield = 0x00000100;
+const GL_STENCIL_BUFFER_BIT: GLbitfield = 0x00000400;
+const GL_COLOR_BUFFER_BIT: GLbitfield = 0x00004000;
+const GL_ADD: GLenum = 0x0104;
+const GL_NEVER: GLenum = 0x0200;
+const GL_LESS: GLenum = 0x0201;
+const GL_EQUAL: GLenum = 0x0202;
+const GL_LEQUAL: GLenum = 0x0203;
+const GL_GREATER: GLenum = 0x0204;
+const GL_NOTEQUAL: GLenum = 0x0205;
+const GL_GEQUAL: GLenum = 0x0206;
+const GL_ALWAYS: GLenum = 0x0207;
+const GL_FRONT_LEFT: GLenum = 0x0400;
+const GL_BACK_LEFT: GLenum = 0x0402;
+const GL_FRONT: GLenum = 0x0404;
+const GL_BACK: GLenum = 0x0405;
+const GL_LEFT: GLenum = 0x0406;
+const GL_FRONT_AND_BACK: GLenum = 0x0408;
+const GL_DEPTH_TEST: GLenum = 0x0b71;
+const GL_BLEND: GLenum = 0x0be2;
+const GL_DRAW_BUFFER: GLenum = 0x0c01;
+const GL_READ_BUFFER: GLenum = 0x0c02;
+const GL_SCISSOR_TEST: GLenum = 0x0c11;
+const GL_DOUBLEBUFFER: GLenum = 0x0c32;
+const GL_UNPACK_SWAP_BYTES: GLenum = 0x0cf0;
+const GL_UNPACK_LSB_FIRST: GLenum = 0x0cf1;
+const GL_UNPACK_ROW_LENGTH: GLenum = 0x0cf2;
+const GL_UNPACK_SKIP_ROWS: GLenum = 0x0cf3;
+const GL_UNPACK_SKIP_PIXELS: GLenum = 0x0cf4;
+const GL_UNPACK_ALIGNMENT: GLenum = 0x0cf5;
+const GL_PACK_SWAP_BYTES: GLenum = 0x0d00;
+const GL_PACK_LSB_FIRST: GLenum = 0x0d01;
+const GL_PACK_ROW_LENGTH: GLenum = 0x0d02;
+const GL_PACK_SKIP_ROWS: GLenum = 0x0d03;
+const GL_PACK_SKIP_PIXELS: GLenum = 0x0d04;
+const GL_PACK_ALIGNMENT: GLenum = 0x0d05;
 const GL_TEXTURE_2D: GLenum = 0x0de1;
+const GL_BYTE: GLenum = 0x1400;
 const GL_UNSIGNED_BYTE: GLenum = 0x1401;
+const GL_SHORT: GLenum = 0x1402;
+const GL_UNSIGNED_SHORT: GLenum = 0x1403;
+const GL_INT: GLenum = 0x1404;
+const GL_UNSIGNED_INT: GLenum = 0x1405;
+const GL_FLOAT: GLenum = 0x1406;
 const GL_MODELVIEW: GLenum = 0x1700;
 const GL_PROJECTION: GLenum = 0x1701;
+const GL_TEXTURE: GLenum = 0x1702;
+const GL_COLOR: GLenum = 0x1800;
+const GL_DEPTH: GLenum = 0x1801;
+const GL_STENCIL: GLenum = 0x1802;
+const GL_STENCIL_INDEX: GLenum = 0x1901;
+const GL_DEPTH_COMPONENT: GLenum = 0x1902;
+const GL_RED: GLenum = 0x1903;
+const GL_GREEN: GLenum = 0x1904;
+const GL_BLUE: GLenum = 0x1905;
+const GL_ALPHA: GLenum = 0x1906;
 const GL_RGB: GLenum = 0x1907;
 const GL_RGBA: GLenum = 0x1908;
 const GL_LUMINANCE: GLenum = 0x1909;
 const GL_LUMINANCE_ALPHA: GLenum = 0x190a;
+const GL_REPLACE: GLenum = 0x1e01;
+const GL_VENDOR: GLenum = 0x1f00;
+const GL_RENDERER: GLenum = 0x1f01;
+const GL_VERSION: GLenum = 0x1f02;
+const GL_EXTENSIONS: GLenum = 0x1f03;
+const GL_MODULATE: GLenum = 0x2100;
+const GL_DECAL: GLenum = 0x2101;
+const GL_TEXTURE_ENV_MODE: GLenum = 0x2200;
+const GL_TEXTURE_ENV_COLOR: GLenum = 0x2201;
+const GL_TEXTURE_ENV: GLenum = 0x2300;
+const GL_ALPHA8: GLenum = 0x803c;
+const GL_LUMINANCE8: GLenum = 0x8040;
+const GL_LUMINANCE8_ALPHA8: GLenum = 0x8045;
+const GL_RGB5: GLenum = 0x8050;
+const GL_RGB8: GLenum = 0x8051;
+const GL_RGBA4: GLenum = 0x8056;
+const GL_RGB5_A1: GLenum = 0x8057;
+const GL_RGBA8: GLenum = 0x8058;
+const GL_BGR: GLenum = 0x80e0;
+const GL_BGRA: GLenum = 0x80e1;
+const GL_SELECTED_TEXTURE_SGIS: GLenum = 0x835c;
+const GL_MAX_TEXTURES_SGIS: GLenum = 0x835d;
+const GL_TEXTURE0_SGIS: GLenum = 0x835e;
+const GL_UNSIGNED_SHORT_5_6_5: GLenum = 0x8363;
+const GL_TEXTURE0_ARB: GLenum = 0x84c0;
+const GL_ACTIVE_TEXTURE_ARB: GLenum = 0x84e0;
+const GL_CLIENT_ACTIVE_TEXTURE_ARB: GLenum = 0x84e1;
+const GL_MAX_TEXTURE_UNITS_ARB: GLenum = 0x84e2;
 
 #[derive(Default)]
 struct Viewport {
@@ -36,43 +166,243 @@ struct Viewport {
     height: f32,
 }
 
-#[derive(Default)]
-struct Texture {
-    width: usize,
-    height: usize,
-    data: Vec<Vec4>,
+/// The current raster position used by glDrawPixels, glBitmap and glCopyPixels.
+#[derive(Copy, Clone)]
+struct RasterPos {
+    /// Window coordinates, with the clip space w.
+    position: Vec4,
+    color: Vec4,
+    valid: bool,
+}
+
+/// A rectangle of values to be drawn at the raster position.
+enum PixelRect {
+    Color(Vec<[f32; 4]>),
+    Depth(Vec<f32>),
+    Stencil(Vec<u32>),
 }
 
 struct GLState {
-    fb: Option<Framebuffer>,
+    fb: Framebuffer,
     matrix_mode: MatrixMode,
     matrix_stacks: [Vec<Mat4>; NUM_MATRIX_MODES],
     viewport: Viewport,
     primitive: Primitive,
-    tex_coord: Vec4,
-    bound_texture: usize,
-    textures: Vec<Texture>,
-    bmi: win32::BITMAPINFOHEADER,
+    color: Vec4,
+    texture_units: [TextureUnit; MAX_TEXTURE_UNITS],
+    active_texture: usize,
+    client_active_texture: usize,
+    namespace: SharedNamespace,
+    raster: RasterPos,
+    pixel_zoom: (f32, f32),
+    clear_color: [f32; 4],
+    clear_depth: f32,
+    clear_stencil: GLint,
+    scissor: Rect,
+    scissor_test: bool,
+    color_mask: [bool; 4],
+    depth_test: bool,
+    depth_func: DepthFunc,
+    depth_range: (f32, f32),
+    depth_mask: bool,
+    stencil_mask: GLuint,
+    draw_buffer: DrawBuffer,
+    read_buffer: ColorBuffer,
+    pack: PixelStore,
+    unpack: PixelStore,
 }
 
 impl Default for GLState {
     fn default() -> Self {
         Self {
-            fb: Default::default(),
+            fb: Framebuffer::new(0, 0, Default::default()),
             matrix_mode: MatrixMode::ModelView,
             matrix_stacks: [vec![Mat4::identity()], vec![Mat4::identity()]],
             viewport: Default::default(),
             primitive: Default::default(),
-            tex_coord: Vec4::new(0.0, 0.0, 0.0, 1.0),
-            bound_texture: 0,
-            textures: vec![Default::default()],
-            bmi: Default::default(),
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            texture_units: Default::default(),
+            active_texture: 0,
+            client_active_texture: 0,
+            namespace: Default::default(),
+            raster: RasterPos {
+                position: Vec4::new(0.0, 0.0, 0.0, 1.0),
+                color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+                valid: true,
+            },
+            pixel_zoom: (1.0, 1.0),
+            clear_color: [0.0; 4],
+            clear_depth: 1.0,
+            clear_stencil: 0,
+            scissor: Default::default(),
+            scissor_test: false,
+            color_mask: [true; 4],
+            depth_test: false,
+            depth_func: DepthFunc::Less,
+            depth_range: (0.0, 1.0),
+            depth_mask: true,
+            stencil_mask: !0,
+            draw_buffer: DrawBuffer::Back,
+            read_buffer: ColorBuffer::Back,
+            pack: Default::default(),
+            unpack: Default::default(),
+        }
+    }
+}
+
+impl GLState {
+    /// Creates the state for a new context whose drawable is `width` x `height` pixels of
+    /// `format`.
+    fn new(width: usize, height: usize, format: PixelFormat) -> Self {
+        // single buffered contexts render straight to the front buffer
+        let (draw_buffer, read_buffer) = if format.double_buffer {
+            (DrawBuffer::Back, ColorBuffer::Back)
+        } else {
+            (DrawBuffer::Front, ColorBuffer::Front)
+        };
+        let mut state = Self {
+            fb: Framebuffer::new(width, height, format),
+            draw_buffer,
+            read_buffer,
+            ..Default::default()
+        };
+        state.reset_viewport();
+        state
+    }
+
+    /// Sets the viewport and scissor box to cover the whole framebuffer, as happens when a
+    /// context is first bound to a drawable.
+    fn reset_viewport(&mut self) {
+        let (width, height) = (self.fb.width, self.fb.height);
+        self.viewport = Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+        };
+        self.scissor = Rect {
+            x: 0,
+            y: 0,
+            width: width as i32,
+            height: height as i32,
+        };
+    }
+
+    /// Reallocates the framebuffer if its size is not `width` x `height`. Like window system
+    /// resizes in other implementations, this leaves the viewport and scissor alone.
+    fn resize(&mut self, width: usize, height: usize) {
+        if (self.fb.width, self.fb.height) != (width, height) {
+            self.fb = Framebuffer::new(width, height, self.fb.format);
+        }
+    }
+
+    fn matrix_stack(&mut self) -> &mut Vec<Mat4> {
+        match self.matrix_mode {
+            MatrixMode::Texture => &mut self.texture_units[self.active_texture].matrix_stack,
+            mode => &mut self.matrix_stacks[mode as usize],
+        }
+    }
+
+    fn fragment_ops(&self) -> FragmentOps {
+        FragmentOps {
+            scissor: self.scissor_test.then_some(self.scissor),
+            // with no depth buffer, the depth test always passes
+            depth_func: (self.depth_test && self.fb.format.depth_bits > 0)
+                .then_some(self.depth_func),
+            depth_mask: self.depth_mask,
+            color_mask: self.color_mask,
+            stencil_mask: self.stencil_mask as u8,
+            draw_buffer: self.draw_buffer,
         }
     }
+
+    /// Maps a clip space position to window coordinates, keeping its w.
+    fn window_coords(&self, clip: Vec4) -> Vec4 {
+        let (near, far) = self.depth_range;
+        Vec4::new(
+            (clip.x / clip.w + 1.0) * self.viewport.width * 0.5 + self.viewport.x,
+            (clip.y / clip.w + 1.0) * self.viewport.height * 0.5 + self.viewport.y,
+            near + (far - near) * (clip.z / clip.w + 1.0) * 0.5,
+            clip.w,
+        )
+    }
+
+    /// Draws `pixels`, a `width` x `height` rectangle listed bottom row first, with its lower
+    /// left corner at the raster position, scaled by the pixel zoom.
+    fn draw_pixel_rect(&mut self, width: usize, height: usize, pixels: &PixelRect) {
+        if !self.raster.valid {
+            return;
+        }
+
+        let ops = self.fragment_ops();
+        let raster = self.raster;
+        let (zoom_x, zoom_y) = self.pixel_zoom;
+        let color = raster.color.as_array().map(to_unorm8);
+        let fb = &mut self.fb;
+
+        // a fragment is produced for every pixel whose center lies inside the zoomed source pixel
+        let span = |origin: f32, zoom: f32, i: usize| {
+            let (a, b) = (origin + zoom * i as f32, origin + zoom * (i + 1) as f32);
+            let (lo, hi) = (a.min(b), a.max(b));
+            (lo - 0.5).ceil() as i32..(hi - 0.5).ceil() as i32
+        };
+
+        for row in 0..height {
+            for y in span(raster.position.y, zoom_y, row) {
+                for col in 0..width {
+                    let index = col + row * width;
+                    for x in span(raster.position.x, zoom_x, col) {
+                        match pixels {
+                            PixelRect::Color(colors) => fb.draw_fragment(
+                                &ops,
+                                x,
+                                y,
+                                raster.position.z,
+                                colors[index].map(to_unorm8),
+                            ),
+                            PixelRect::Depth(depths) => {
+                                fb.draw_fragment(&ops, x, y, depths[index].clamp(0.0, 1.0), color)
+                            }
+                            PixelRect::Stencil(stencils) => {
+                                fb.draw_stencil(&ops, x, y, stencils[index] as u8)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns how many bytes of client memory a `width` x `height` image is read from with the current
+/// unpack parameters, or 0 if the format or type is not supported.
+fn unpack_size(format: GLenum, type_: GLenum, width: GLsizei, height: GLsizei) -> usize {
+    with_state(|state| {
+        Layout::new(
+            &state.unpack,
+            format,
+            type_,
+            width.max(0) as usize,
+            height.max(0) as usize,
+        )
+        .map_or(0, |layout| layout.size())
+    })
+}
+
+/// Like `unpack_size`, for glBitmap's one bit per pixel images.
+fn unpack_bitmap_size(width: GLsizei, height: GLsizei) -> usize {
+    with_state(|state| {
+        BitmapLayout::new(&state.unpack, width.max(0) as usize, height.max(0) as usize).size()
+    })
+}
+
+fn to_unorm8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
 }
 
-thread_local! {
-    static GL_STATE: RefCell<GLState> = RefCell::new(Default::default());
+/// Runs `f` on the state of the calling thread's current context, if there is one.
+fn with_state<R: Default>(f: impl FnOnce(&mut GLState) -> R) -> R {
+    context::with_current(|context| f(&mut context.state))
 }
 
 #[repr(u32)]
@@ -80,10 +410,21 @@ thread_local! {
 pub enum MatrixMode {
     ModelView,
     Projection,
+    Texture,
 }
 
+/// Number of matrix modes with a stack in `GLState::matrix_stacks`. Texture matrix stacks belong
+/// to their texture units.
 const NUM_MATRIX_MODES: usize = 2;
 
+/// Maps a GL_TEXTUREi_ARB or GL_TEXTUREi_SGIS enum to a texture unit index.
+fn texture_unit(texture: GLenum) -> Option<usize> {
+    [GL_TEXTURE0_ARB, GL_TEXTURE0_SGIS]
+        .into_iter()
+        .find(|&base| (base..base + MAX_TEXTURE_UNITS as GLenum).contains(&texture))
+        .map(|base| (texture - base) as usize)
+}
+
 #[repr(u32)]
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub enum PrimitiveMode {
@@ -109,172 +450,557 @@ struct Primitive {
 #[derive(Clone, Copy)]
 struct Vertex {
     position: Vec4,
-    tex_coord: Vec4,
+    color: Vec4,
+    tex_coords: [Vec4; MAX_TEXTURE_UNITS],
+}
+
+impl Vertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let lerp = |a: Vec4, b: Vec4| a + t * (b - a);
+        Self {
+            position: lerp(self.position, other.position),
+            color: lerp(self.color, other.color),
+            tex_coords: std::array::from_fn(|i| lerp(self.tex_coords[i], other.tex_coords[i])),
+        }
+    }
 }
 
+/// Sets the gamma ramp applied to every context's color buffer when it is presented. `ramp`
+/// points to 256 entries each for red, green and blue, like SetDeviceGammaRamp's argument. The
+/// ramp is shared by all contexts, the way the display's would be.
 #[no_mangle]
-pub extern "system" fn wglCreateContext(hdc: win32::HDC) -> win32::HGLRC {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+pub extern "system" fn mglSetGammaRamp(ramp: *const GLushort) {
+    trace!(mglSetGammaRamp, unsafe { trace::slice(ramp, 3 * 256) });
+    if let Some(ramp) = unsafe { (ramp as *const gamma::GammaRamp).as_ref() } {
+        gamma::set(ramp);
+    }
+}
 
-        let mut rect: win32::RECT = Default::default();
-        unsafe {
-            win32::GetClientRect(win32::WindowFromDC(hdc), &mut rect as _);
-        }
-        let (width, height) = (rect.right, rect.bottom);
-        state.fb = Some(Framebuffer::new(width as usize, height as usize));
-
-        state.bmi = win32::BITMAPINFOHEADER {
-            size: std::mem::size_of::<win32::BITMAPINFOHEADER>() as u32,
-            width,
-            height,
-            planes: 1,
-            bit_count: 32,
-            compression: win32::BI_RGB,
-            size_image: 0,
-            x_pels_per_meter: 0,
-            y_pels_per_meter: 0,
-            clr_used: 0,
-            clr_important: 0,
+/// Copies the current gamma ramp to `ramp`, in the layout mglSetGammaRamp takes.
+#[no_mangle]
+pub extern "system" fn mglGetGammaRamp(ramp: *mut GLushort) {
+    trace!(mglGetGammaRamp);
+    if let Some(ramp) = unsafe { (ramp as *mut gamma::GammaRamp).as_mut() } {
+        *ramp = gamma::get();
+    }
+}
+
+/// Tells the current context that its drawable is now `width` x `height` pixels, for window
+/// systems where minigl cannot find out for itself. The contents of the framebuffer are undefined
+/// afterwards if the size changed.
+#[no_mangle]
+pub extern "system" fn mglSetDrawableSize(width: GLsizei, height: GLsizei) {
+    trace!(mglSetDrawableSize, width, height);
+    if width < 0 || height < 0 {
+        return;
+    }
+    context::with_current(|context| context.resize(width as usize, height as usize));
+}
+
+/// Has `callback` called with every frame the current context swaps, after any earlier callbacks.
+/// Returns an id for mglRemoveFrameCallback, or 0 if `callback` is null or there is no current
+/// context.
+#[no_mangle]
+pub extern "system" fn mglAddFrameCallback(
+    callback: Option<sink::FrameCallback>,
+    user_data: *mut c_void,
+) -> GLuint {
+    // the callback cannot be replayed, but its address tells apart the ones a trace adds
+    let address = callback.map_or(0, |callback| callback as usize);
+    trace!(mglAddFrameCallback, address, user_data as usize);
+    let Some(callback) = callback else {
+        return 0;
+    };
+    let sink = sink::CallbackSink {
+        callback,
+        user_data: user_data as usize,
+    };
+    context::with_current(|context| context.add_sink(Box::new(sink)))
+}
+
+#[no_mangle]
+pub extern "system" fn mglRemoveFrameCallback(id: GLuint) {
+    trace!(mglRemoveFrameCallback, id);
+    context::with_current(|context| context.remove_sink(id));
+}
+
+#[no_mangle]
+pub extern "system" fn glFlush() {
+    trace!(glFlush);
+    context::with_current(|context| context.flush());
+}
+
+/// Like glFlush, but would also have to wait for queued work to complete rather than just
+/// submitting it.
+#[no_mangle]
+pub extern "system" fn glFinish() {
+    trace!(glFinish);
+    glFlush();
+}
+
+#[no_mangle]
+pub extern "system" fn glGetString(name: GLenum) -> *const GLubyte {
+    trace!(glGetString, name);
+    let string = match name {
+        GL_VENDOR => c"minigl".as_ptr(),
+        GL_RENDERER => c"minigl software rasterizer".as_ptr(),
+        GL_VERSION => c"1.1 minigl".as_ptr(),
+        GL_EXTENSIONS => procs::extensions().as_ptr(),
+        _ => std::ptr::null(),
+    };
+    string as *const GLubyte
+}
+
+#[no_mangle]
+pub extern "system" fn glClearColor(
+    red: GLclampf,
+    green: GLclampf,
+    blue: GLclampf,
+    alpha: GLclampf,
+) {
+    trace!(glClearColor, red, green, blue, alpha);
+    with_state(|state| {
+        state.clear_color = [red, green, blue, alpha].map(|c| c.clamp(0.0, 1.0));
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glClearDepth(depth: GLclampd) {
+    trace!(glClearDepth, depth);
+    with_state(|state| {
+        state.clear_depth = depth.clamp(0.0, 1.0) as f32;
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glClearStencil(s: GLint) {
+    trace!(glClearStencil, s);
+    with_state(|state| {
+        state.clear_stencil = s;
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glClear(mask: GLbitfield) {
+    trace!(glClear, mask);
+    with_state(|state| {
+        let fb = &mut state.fb;
+
+        let rect = if state.scissor_test {
+            state.scissor
+        } else {
+            Rect {
+                x: 0,
+                y: 0,
+                width: fb.width as i32,
+                height: fb.height as i32,
+            }
         };
+
+        if mask & GL_COLOR_BUFFER_BIT != 0 && state.color_mask != [false; 4] {
+            let color = state.clear_color.map(to_unorm8);
+            fb.clear_color(state.draw_buffer, rect, color, state.color_mask);
+        }
+
+        if mask & GL_DEPTH_BUFFER_BIT != 0 && state.depth_mask {
+            fb.clear_depth(rect, state.clear_depth);
+        }
+
+        if mask & GL_STENCIL_BUFFER_BIT != 0 {
+            fb.clear_stencil(rect, state.clear_stencil as u8, state.stencil_mask as u8);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glScissor(x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+    trace!(glScissor, x, y, width, height);
+    with_state(|state| {
+        state.scissor = Rect {
+            x,
+            y,
+            width: width.max(0),
+            height: height.max(0),
+        };
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glColorMask(
+    red: GLboolean,
+    green: GLboolean,
+    blue: GLboolean,
+    alpha: GLboolean,
+) {
+    trace!(glColorMask, red, green, blue, alpha);
+    with_state(|state| {
+        state.color_mask = [red != 0, green != 0, blue != 0, alpha != 0];
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glStencilMask(mask: GLuint) {
+    trace!(glStencilMask, mask);
+    with_state(|state| {
+        state.stencil_mask = mask;
     });
+}
+
+#[no_mangle]
+pub extern "system" fn glCullFace(_mode: GLenum) {
+    trace!(glCullFace, _mode);
+}
 
-    1
+fn set_capability(cap: GLenum, enabled: bool) {
+    with_state(|state| match cap {
+        GL_DEPTH_TEST => state.depth_test = enabled,
+        GL_SCISSOR_TEST => state.scissor_test = enabled,
+        GL_TEXTURE_2D => {
+            let unit = state.active_texture;
+            state.texture_units[unit].enabled = enabled;
+        }
+        _ => {}
+    });
 }
 
 #[no_mangle]
-pub extern "system" fn wglMakeCurrent(_hdc: win32::HDC, _hglrc: win32::HGLRC) -> win32::BOOL {
-    true
+pub extern "system" fn glEnable(cap: GLenum) {
+    trace!(glEnable, cap);
+    set_capability(cap, true);
 }
 
 #[no_mangle]
-pub extern "system" fn wglGetCurrentContext() -> win32::HGLRC {
-    0
+pub extern "system" fn glDisable(cap: GLenum) {
+    trace!(glDisable, cap);
+    set_capability(cap, false);
 }
 
 #[no_mangle]
-pub extern "system" fn wglGetCurrentDC() -> win32::HDC {
-    0
+pub extern "system" fn glAlphaFunc(_func: GLenum, _ref: GLclampf) {
+    trace!(glAlphaFunc, _func, _ref);
 }
 
 #[no_mangle]
-pub extern "system" fn wglDeleteContext(_hglrc: win32::HGLRC) -> win32::BOOL {
-    true
+pub extern "system" fn glBlendFunc(_sfactor: GLenum, _dfactor: GLenum) {
+    trace!(glBlendFunc, _sfactor, _dfactor);
 }
 
 #[no_mangle]
-pub extern "system" fn wglGetProcAddress(_proc: win32::LPCSTR) -> win32::PROC {
-    None
+pub extern "system" fn glDepthFunc(func: GLenum) {
+    trace!(glDepthFunc, func);
+    with_state(|state| {
+        state.depth_func = match func {
+            GL_NEVER => DepthFunc::Never,
+            GL_LESS => DepthFunc::Less,
+            GL_EQUAL => DepthFunc::Equal,
+            GL_LEQUAL => DepthFunc::LEqual,
+            GL_GREATER => DepthFunc::Greater,
+            GL_NOTEQUAL => DepthFunc::NotEqual,
+            GL_GEQUAL => DepthFunc::GEqual,
+            GL_ALWAYS => DepthFunc::Always,
+            _ => return,
+        };
+    });
 }
 
 #[no_mangle]
-pub extern "system" fn wglChoosePixelFormat(_hdc: win32::HDC, _ppfd: *const c_void) -> win32::BOOL {
-    true
+pub extern "system" fn glDepthRange(near_val: GLclampd, far_val: GLclampd) {
+    trace!(glDepthRange, near_val, far_val);
+    with_state(|state| {
+        state.depth_range = (
+            near_val.clamp(0.0, 1.0) as f32,
+            far_val.clamp(0.0, 1.0) as f32,
+        );
+    });
 }
 
 #[no_mangle]
-pub extern "system" fn wglSetPixelFormat(
-    _hdc: win32::HDC,
-    _format: i32,
-    _ppfd: *const c_void,
-) -> win32::BOOL {
-    true
+pub extern "system" fn glDepthMask(flag: GLboolean) {
+    trace!(glDepthMask, flag);
+    with_state(|state| {
+        state.depth_mask = flag != 0;
+    });
 }
 
 #[no_mangle]
-pub extern "system" fn wglSwapBuffers(hdc: win32::HDC) -> win32::BOOL {
-    GL_STATE.with(|state| {
-        let state = state.borrow();
-        let fb = state.fb.as_ref().unwrap();
+pub extern "system" fn glPolygonMode(_face: GLenum, _mode: GLenum) {
+    trace!(glPolygonMode, _face, _mode);
+}
 
-        unsafe {
-            win32::StretchDIBits(
-                hdc,
-                0,
-                0,
-                fb.width as i32,
-                fb.height as i32,
-                0,
-                0,
-                fb.width as i32,
-                fb.height as i32,
-                fb.buffer.as_ptr() as *const c_void,
-                &state.bmi as _,
-                win32::DIB_RGB_COLORS,
-                win32::SRCCOPY,
-            );
+#[no_mangle]
+pub extern "system" fn glShadeModel(_mode: GLenum) {
+    trace!(glShadeModel, _mode);
+}
+
+#[no_mangle]
+pub extern "system" fn glTexParameterf(_target: GLenum, _pname: GLenum, _param: GLfloat) {
+    trace!(glTexParameterf, _target, _pname, _param);
+}
+
+#[no_mangle]
+pub extern "system" fn glTexEnvi(target: GLenum, pname: GLenum, param: GLint) {
+    trace!(glTexEnvi, target, pname, param);
+    with_state(|state| {
+        if target != GL_TEXTURE_ENV || pname != GL_TEXTURE_ENV_MODE {
+            return;
         }
+        let mode = match param as GLenum {
+            GL_MODULATE => TexEnvMode::Modulate,
+            GL_REPLACE => TexEnvMode::Replace,
+            GL_DECAL => TexEnvMode::Decal,
+            GL_BLEND => TexEnvMode::Blend,
+            GL_ADD => TexEnvMode::Add,
+            _ => return,
+        };
+        state.texture_units[state.active_texture].env.mode = mode;
     });
-    true
 }
 
 #[no_mangle]
-pub extern "system" fn glGetString(_name: GLenum) -> *const GLubyte {
-    b"asdf\0".as_ptr()
+pub extern "system" fn glTexEnvf(target: GLenum, pname: GLenum, param: GLfloat) {
+    trace!(glTexEnvf, target, pname, param);
+    glTexEnvi(target, pname, param as GLint);
 }
 
 #[no_mangle]
-pub extern "system" fn glClearColor(
-    _red: GLclampf,
-    _green: GLclampf,
-    _blue: GLclampf,
-    _alpha: GLclampf,
-) {
+pub extern "system" fn glTexEnvfv(target: GLenum, pname: GLenum, params: *const GLfloat) {
+    let count = if pname == GL_TEXTURE_ENV_COLOR { 4 } else { 1 };
+    trace!(glTexEnvfv, target, pname, unsafe {
+        trace::slice(params, count)
+    });
+    if params.is_null() {
+        return;
+    }
+    if pname != GL_TEXTURE_ENV_COLOR {
+        glTexEnvf(target, pname, unsafe { *params });
+        return;
+    }
+    with_state(|state| {
+        if target != GL_TEXTURE_ENV {
+            return;
+        }
+        let params = unsafe { std::slice::from_raw_parts(params, 4) };
+        state.texture_units[state.active_texture].env.color =
+            Vec4::from_array([0, 1, 2, 3].map(|i| params[i].clamp(0.0, 1.0)));
+    });
 }
 
 #[no_mangle]
-pub extern "system" fn glClear(_mask: GLbitfield) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
-        state.fb.as_mut().unwrap().clear();
+pub extern "system" fn glTexEnviv(target: GLenum, pname: GLenum, params: *const GLint) {
+    let count = if pname == GL_TEXTURE_ENV_COLOR { 4 } else { 1 };
+    trace!(glTexEnviv, target, pname, unsafe {
+        trace::slice(params, count)
     });
+    if params.is_null() {
+        return;
+    }
+    if pname != GL_TEXTURE_ENV_COLOR {
+        glTexEnvi(target, pname, unsafe { *params });
+        return;
+    }
+    // integer colors map the full GLint range onto [-1, 1]
+    let params = unsafe { std::slice::from_raw_parts(params, 4) };
+    let color = [0, 1, 2, 3].map(|i| (params[i] as f64 / GLint::MAX as f64) as GLfloat);
+    glTexEnvfv(target, pname, color.as_ptr());
 }
 
 #[no_mangle]
-pub extern "system" fn glCullFace(_mode: GLenum) {}
+pub extern "system" fn glActiveTextureARB(texture: GLenum) {
+    trace!(glActiveTextureARB, texture);
+    with_state(|state| {
+        if let Some(unit) = texture_unit(texture) {
+            state.active_texture = unit;
+        }
+    });
+}
 
 #[no_mangle]
-pub extern "system" fn glEnable(_cap: GLenum) {}
+pub extern "system" fn glClientActiveTextureARB(texture: GLenum) {
+    trace!(glClientActiveTextureARB, texture);
+    with_state(|state| {
+        if let Some(unit) = texture_unit(texture) {
+            state.client_active_texture = unit;
+        }
+    });
+}
 
 #[no_mangle]
-pub extern "system" fn glDisable(_cap: GLenum) {}
+pub extern "system" fn glMultiTexCoord4fARB(
+    target: GLenum,
+    s: GLfloat,
+    t: GLfloat,
+    r: GLfloat,
+    q: GLfloat,
+) {
+    trace!(glMultiTexCoord4fARB, target, s, t, r, q);
+    with_state(|state| {
+        if let Some(unit) = texture_unit(target) {
+            state.texture_units[unit].tex_coord = Vec4::new(s, t, r, q);
+        }
+    });
+}
 
 #[no_mangle]
-pub extern "system" fn glAlphaFunc(_func: GLenum, _ref: GLclampf) {}
+pub extern "system" fn glMultiTexCoord1fARB(target: GLenum, s: GLfloat) {
+    trace!(glMultiTexCoord1fARB, target, s);
+    glMultiTexCoord4fARB(target, s, 0.0, 0.0, 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glBlendFunc(_sfactor: GLenum, _dfactor: GLenum) {}
+pub extern "system" fn glMultiTexCoord2fARB(target: GLenum, s: GLfloat, t: GLfloat) {
+    trace!(glMultiTexCoord2fARB, target, s, t);
+    glMultiTexCoord4fARB(target, s, t, 0.0, 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glDepthFunc(_func: GLenum) {}
+pub extern "system" fn glMultiTexCoord3fARB(target: GLenum, s: GLfloat, t: GLfloat, r: GLfloat) {
+    trace!(glMultiTexCoord3fARB, target, s, t, r);
+    glMultiTexCoord4fARB(target, s, t, r, 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glDepthRange(_near_val: GLdouble, _far_val: GLdouble) {}
+pub extern "system" fn glMultiTexCoord2fvARB(target: GLenum, v: &[GLfloat; 2]) {
+    trace!(glMultiTexCoord2fvARB, target, v);
+    glMultiTexCoord4fARB(target, v[0], v[1], 0.0, 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glDepthMask(_flag: GLboolean) {}
+pub extern "system" fn glMultiTexCoord3fvARB(target: GLenum, v: &[GLfloat; 3]) {
+    trace!(glMultiTexCoord3fvARB, target, v);
+    glMultiTexCoord4fARB(target, v[0], v[1], v[2], 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glPolygonMode(_face: GLenum, _mode: GLenum) {}
+pub extern "system" fn glMultiTexCoord4fvARB(target: GLenum, v: &[GLfloat; 4]) {
+    trace!(glMultiTexCoord4fvARB, target, v);
+    glMultiTexCoord4fARB(target, v[0], v[1], v[2], v[3]);
+}
 
 #[no_mangle]
-pub extern "system" fn glShadeModel(_mode: GLenum) {}
+pub extern "system" fn glSelectTextureSGIS(target: GLenum) {
+    trace!(glSelectTextureSGIS, target);
+    glActiveTextureARB(target);
+}
 
 #[no_mangle]
-pub extern "system" fn glTexParameterf(_target: GLenum, _pname: GLenum, _param: GLfloat) {}
+pub extern "system" fn glMTexCoord2fSGIS(target: GLenum, s: GLfloat, t: GLfloat) {
+    trace!(glMTexCoord2fSGIS, target, s, t);
+    glMultiTexCoord4fARB(target, s, t, 0.0, 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glTexEnvf(_target: GLenum, _pname: GLenum, _param: GLfloat) {}
+pub extern "system" fn glMTexCoord2fvSGIS(target: GLenum, v: &[GLfloat; 2]) {
+    trace!(glMTexCoord2fvSGIS, target, v);
+    glMultiTexCoord4fARB(target, v[0], v[1], 0.0, 1.0);
+}
 
 #[no_mangle]
 pub extern "system" fn glBindTexture(_target: GLenum, texture: GLuint) {
-    GL_STATE.with(|state| {
-        let state = &mut *state.borrow_mut();
-        state.bound_texture = texture as usize;
-        if state.bound_texture >= state.textures.len() {
-            state
-                .textures
-                .resize_with(state.bound_texture + 1, Default::default)
+    trace!(glBindTexture, _target, texture);
+    with_state(|state| {
+        state.texture_units[state.active_texture].bound_texture = texture;
+        state.namespace.lock().textures.get_or_create(texture);
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glGenTextures(n: GLsizei, textures: *mut GLuint) {
+    let mut recording = trace::begin(trace::Call::glGenTextures).map(|trace| trace.arg(n));
+    with_state(|state| {
+        if n <= 0 || textures.is_null() {
+            return;
+        }
+        let textures = unsafe { std::slice::from_raw_parts_mut(textures, n as usize) };
+        let mut namespace = state.namespace.lock();
+        for texture in textures {
+            *texture = namespace.textures.generate();
+        }
+    });
+    // the names go after the arguments, so a replay can check it gets the same ones
+    if let Some(recording) = &mut recording {
+        recording.push(unsafe { trace::slice(textures, n.max(0) as usize) });
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn glDeleteTextures(n: GLsizei, textures: *const GLuint) {
+    trace!(glDeleteTextures, n, unsafe {
+        trace::slice(textures, n.max(0) as usize)
+    });
+    with_state(|state| {
+        if n <= 0 || textures.is_null() {
+            return;
+        }
+        let textures = unsafe { std::slice::from_raw_parts(textures, n as usize) };
+        let mut namespace = state.namespace.lock();
+        for &texture in textures {
+            for unit in &mut state.texture_units {
+                if unit.bound_texture == texture {
+                    unit.bound_texture = 0;
+                }
+            }
+            namespace.textures.delete(texture);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glIsTexture(texture: GLuint) -> GLboolean {
+    trace!(glIsTexture, texture);
+    with_state(|state| state.namespace.lock().textures.is_texture(texture) as GLboolean)
+}
+
+#[no_mangle]
+pub extern "system" fn glAreTexturesResident(
+    n: GLsizei,
+    textures: *const GLuint,
+    residences: *mut GLboolean,
+) -> GLboolean {
+    trace!(glAreTexturesResident, n, unsafe {
+        trace::slice(textures, n.max(0) as usize)
+    });
+    with_state(|state| {
+        if n <= 0 || textures.is_null() {
+            return GL_FALSE;
+        }
+        // everything lives in system memory, so every texture object is always resident
+        let textures = unsafe { std::slice::from_raw_parts(textures, n as usize) };
+        let namespace = state.namespace.lock();
+        let all_resident = textures.iter().all(|&t| namespace.textures.is_texture(t));
+        if !all_resident && !residences.is_null() {
+            let residences = unsafe { std::slice::from_raw_parts_mut(residences, n as usize) };
+            for (residence, &texture) in residences.iter_mut().zip(textures) {
+                *residence = namespace.textures.is_texture(texture) as GLboolean;
+            }
+        }
+        all_resident as GLboolean
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn glPrioritizeTextures(
+    n: GLsizei,
+    textures: *const GLuint,
+    priorities: *const GLclampf,
+) {
+    let len = n.max(0) as usize;
+    trace!(
+        glPrioritizeTextures,
+        n,
+        unsafe { trace::slice(textures, len) },
+        unsafe { trace::slice(priorities, len) },
+    );
+    with_state(|state| {
+        if n <= 0 || textures.is_null() || priorities.is_null() {
+            return;
+        }
+        let textures = unsafe { std::slice::from_raw_parts(textures, n as usize) };
+        let priorities = unsafe { std::slice::from_raw_parts(priorities, n as usize) };
+        let mut namespace = state.namespace.lock();
+        for (&texture, &priority) in textures.iter().zip(priorities) {
+            if namespace.textures.is_texture(texture) {
+                namespace.textures.get_or_create(texture).priority = priority.clamp(0.0, 1.0);
+            }
         }
     });
 }
@@ -291,56 +1017,62 @@ pub extern "system" fn glTexImage2D(
     type_: GLenum,
     data: *const GLvoid,
 ) {
-    GL_STATE.with(|state| {
-        let state = &mut *state.borrow_mut();
-
-        let internal_format = match internal_format {
-            1 => GL_LUMINANCE,
-            2 => GL_LUMINANCE_ALPHA,
-            3 => GL_RGB,
-            4 => GL_RGBA,
-            _ => internal_format as GLenum,
+    trace!(
+        glTexImage2D,
+        target,
+        level,
+        internal_format,
+        width,
+        height,
+        _border,
+        format,
+        type_,
+        unsafe { trace::slice(data as *const u8, unpack_size(format, type_, width, height)) },
+    );
+    with_state(|state| {
+        let base_format = match internal_format as GLenum {
+            1 | GL_LUMINANCE | GL_LUMINANCE8 => BaseFormat::Luminance,
+            2 | GL_LUMINANCE_ALPHA | GL_LUMINANCE8_ALPHA8 => BaseFormat::LuminanceAlpha,
+            3 | GL_RGB | GL_RGB5 | GL_RGB8 => BaseFormat::Rgb,
+            4 | GL_RGBA | GL_RGBA4 | GL_RGB5_A1 | GL_RGBA8 => BaseFormat::Rgba,
+            GL_ALPHA | GL_ALPHA8 => BaseFormat::Alpha,
+            _ => return,
         };
 
-        // we only care about what quake uses (for now, at least)
         assert!(target == GL_TEXTURE_2D);
-        assert!(
-            internal_format == GL_RGB
-                || internal_format == GL_RGBA
-                || internal_format == GL_LUMINANCE
-        );
-        assert!(format == GL_RGBA || format == GL_LUMINANCE);
-        assert_eq!(type_, GL_UNSIGNED_BYTE);
 
-        if format != GL_RGBA || level != 0 {
+        // TODO mipmaps
+        if level != 0 || format == GL_DEPTH_COMPONENT || format == GL_STENCIL_INDEX {
             return;
         }
+        let Some(layout) = Layout::new(
+            &state.unpack,
+            format,
+            type_,
+            width.max(0) as usize,
+            height.max(0) as usize,
+        ) else {
+            return;
+        };
 
-        let texture = &mut state.textures[state.bound_texture];
-        texture.width = width as usize;
-        texture.height = height as usize;
+        let mut namespace = state.namespace.lock();
+        let texture = namespace
+            .textures
+            .get_or_create(state.texture_units[state.active_texture].bound_texture);
+        texture.width = layout.width;
+        texture.height = layout.height;
+        texture.base_format = base_format;
         texture
             .data
             .resize_with(texture.width * texture.height, Default::default);
 
-        if !data.is_null() {
-            let data = unsafe {
-                std::slice::from_raw_parts(
-                    data as *const GLubyte,
-                    width as usize * height as usize * 4,
-                )
-            };
-
-            for y in 0..height {
-                for x in 0..width {
-                    let index = ((x + y * width) * 4) as usize;
-                    let color = Vec4::new(
-                        data[index] as f32 / 256.0,
-                        data[index + 1] as f32 / 256.0,
-                        data[index + 2] as f32 / 256.0,
-                        data[index + 3] as f32 / 256.0,
-                    );
-                    texture.data[(x + y * width) as usize] = color;
+        if !data.is_null() && layout.size() > 0 {
+            let data = unsafe { std::slice::from_raw_parts(data as *const GLubyte, layout.size()) };
+
+            for y in 0..layout.height {
+                for x in 0..layout.width {
+                    let color = layout.get_color(&data[layout.pixel_offset(x, y)..]);
+                    texture.data[x + y * layout.width] = base_format.convert(color);
                 }
             }
         }
@@ -349,8 +1081,8 @@ pub extern "system" fn glTexImage2D(
 
 #[no_mangle]
 pub extern "system" fn glViewport(x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+    trace!(glViewport, x, y, width, height);
+    with_state(|state| {
         state.viewport = Viewport {
             x: x as f32,
             y: y as f32,
@@ -362,11 +1094,12 @@ pub extern "system" fn glViewport(x: GLint, y: GLint, width: GLsizei, height: GL
 
 #[no_mangle]
 pub extern "system" fn glMatrixMode(mode: GLenum) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+    trace!(glMatrixMode, mode);
+    with_state(|state| {
         state.matrix_mode = match mode {
             GL_MODELVIEW => MatrixMode::ModelView,
             GL_PROJECTION => MatrixMode::Projection,
+            GL_TEXTURE => MatrixMode::Texture,
             _ => todo!(),
         };
     })
@@ -374,11 +1107,9 @@ pub extern "system" fn glMatrixMode(mode: GLenum) {
 
 #[no_mangle]
 pub extern "system" fn glLoadIdentity() {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
-
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+    trace!(glLoadIdentity);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(top) = stack.last_mut() {
             *top = Mat4::identity();
         }
@@ -394,11 +1125,9 @@ pub extern "system" fn glOrtho(
     near_val: GLdouble,
     far_val: GLdouble,
 ) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
-
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+    trace!(glOrtho, left, right, bottom, top, near_val, far_val);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(stack_top) = stack.last_mut() {
             let (left, right) = (left as f32, right as f32);
             let (bottom, top) = (bottom as f32, top as f32);
@@ -425,11 +1154,9 @@ pub extern "system" fn glFrustum(
     near_val: GLdouble,
     far_val: GLdouble,
 ) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
-
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+    trace!(glFrustum, left, right, bottom, top, near_val, far_val);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(stack_top) = stack.last_mut() {
             let (left, right) = (left as f32, right as f32);
             let (bottom, top) = (bottom as f32, top as f32);
@@ -448,21 +1175,40 @@ pub extern "system" fn glFrustum(
 }
 
 #[no_mangle]
-pub extern "system" fn glColor3f(_red: GLfloat, _green: GLfloat, _blue: GLfloat) {}
+pub extern "system" fn glColor3f(red: GLfloat, green: GLfloat, blue: GLfloat) {
+    trace!(glColor3f, red, green, blue);
+    glColor4f(red, green, blue, 1.0);
+}
 
 #[no_mangle]
-pub extern "system" fn glColor3ubv(_v: *const GLubyte) {}
+pub extern "system" fn glColor3ubv(v: &[GLubyte; 3]) {
+    trace!(glColor3ubv, v);
+    glColor4f(
+        v[0] as f32 / 255.0,
+        v[1] as f32 / 255.0,
+        v[2] as f32 / 255.0,
+        1.0,
+    );
+}
 
 #[no_mangle]
-pub extern "system" fn glColor4f(_red: GLfloat, _green: GLfloat, _blue: GLfloat, _alpha: GLfloat) {}
+pub extern "system" fn glColor4f(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat) {
+    trace!(glColor4f, red, green, blue, alpha);
+    with_state(|state| {
+        state.color = Vec4::new(red, green, blue, alpha);
+    });
+}
 
 #[no_mangle]
-pub extern "system" fn glColor4fv(_v: *const GLfloat) {}
+pub extern "system" fn glColor4fv(v: &[GLfloat; 4]) {
+    trace!(glColor4fv, v);
+    glColor4f(v[0], v[1], v[2], v[3]);
+}
 
 #[no_mangle]
 pub extern "system" fn glBegin(mode: PrimitiveMode) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+    trace!(glBegin, mode);
+    with_state(|state| {
         state.primitive.mode = mode;
         state.primitive.vertices.clear();
     });
@@ -470,8 +1216,8 @@ pub extern "system" fn glBegin(mode: PrimitiveMode) {
 
 #[no_mangle]
 pub extern "system" fn glEnd() {
-    GL_STATE.with(|state| {
-        let state = &mut *state.borrow_mut();
+    trace!(glEnd);
+    with_state(|state| {
         let verts = &mut state.primitive.vertices;
 
         let m = *state.matrix_stacks[MatrixMode::Projection as usize]
@@ -481,28 +1227,44 @@ pub extern "system" fn glEnd() {
                 .last()
                 .unwrap();
 
-        verts
-            .iter_mut()
-            .for_each(|vert| vert.position = m * vert.position);
+        let texture_matrices = state
+            .texture_units
+            .each_ref()
+            .map(|unit| *unit.matrix_stack.last().unwrap());
+
+        for vert in verts.iter_mut() {
+            vert.position = m * vert.position;
+            for (tex_coord, &matrix) in vert.tex_coords.iter_mut().zip(&texture_matrices) {
+                *tex_coord = matrix * *tex_coord;
+            }
+        }
 
         let mut polys = vec![];
 
+        // vertices left over after the last whole primitive are dropped, as GL requires
         match state.primitive.mode {
             PrimitiveMode::Triangles => {
-                for i in (0..verts.len()).step_by(3) {
-                    polys.push(vec![verts[i], verts[i + 1], verts[i + 2]]);
+                for tri in verts.chunks_exact(3) {
+                    polys.push(tri.to_vec());
                 }
             }
 
             PrimitiveMode::Quads => {
-                for i in (0..verts.len()).step_by(4) {
-                    polys.push(vec![verts[i], verts[i + 1], verts[i + 2]]);
-                    polys.push(vec![verts[i + 2], verts[i + 3], verts[i]]);
+                for quad in verts.chunks_exact(4) {
+                    polys.push(vec![quad[0], quad[1], quad[2]]);
+                    polys.push(vec![quad[2], quad[3], quad[0]]);
+                }
+            }
+
+            PrimitiveMode::QuadStrip => {
+                for i in (0..(verts.len() & !1).saturating_sub(2)).step_by(2) {
+                    polys.push(vec![verts[i], verts[i + 1], verts[i + 3]]);
+                    polys.push(vec![verts[i + 3], verts[i + 2], verts[i]]);
                 }
             }
 
             PrimitiveMode::TriangleStrip => {
-                for i in 0..verts.len() - 2 {
+                for i in 0..verts.len().saturating_sub(2) {
                     if i % 2 == 0 {
                         polys.push(vec![verts[i], verts[i + 1], verts[i + 2]]);
                     } else {
@@ -512,7 +1274,7 @@ pub extern "system" fn glEnd() {
             }
 
             PrimitiveMode::TriangleFan => {
-                for i in 1..verts.len() - 1 {
+                for i in 1..verts.len().saturating_sub(1) {
                     polys.push(vec![verts[0], verts[i], verts[i + 1]]);
                 }
             }
@@ -521,7 +1283,11 @@ pub extern "system" fn glEnd() {
                 polys.push(verts.clone());
             }
 
-            _ => todo!(),
+            // TODO points and lines
+            PrimitiveMode::Points
+            | PrimitiveMode::Lines
+            | PrimitiveMode::LineStrip
+            | PrimitiveMode::LineLoop => {}
         }
 
         // TODO simplify clipping
@@ -551,12 +1317,7 @@ pub extern "system" fn glEnd() {
                     }
                     if a_dot.signum() != b_dot.signum() {
                         let t = -a_dot / (b_dot - a_dot);
-                        let v = a.position + t * (b.position - a.position);
-                        let uv = a.tex_coord + t * (b.tex_coord - a.tex_coord);
-                        clipped_poly.push(Vertex {
-                            position: v,
-                            tex_coord: uv,
-                        });
+                        clipped_poly.push(a.lerp(&b, t));
                     }
                 }
                 poly = clipped_poly;
@@ -573,34 +1334,41 @@ pub extern "system" fn glEnd() {
             }
         }
 
+        let ops = state.fragment_ops();
+
+        // texture units are evaluated in order, each one's output feeding the next
+        let namespace = state.namespace.lock();
+        let units: Vec<_> = state
+            .texture_units
+            .iter()
+            .enumerate()
+            .filter(|(_, unit)| unit.enabled)
+            .filter_map(|(i, unit)| {
+                let texture = namespace.textures.get(unit.bound_texture)?;
+                texture.is_complete().then_some((i, unit.env, texture))
+            })
+            .collect();
+
         for tri in &mut tris {
             for vert in tri.iter_mut() {
-                vert.position.x /= vert.position.w;
-                vert.position.y /= vert.position.w;
-                vert.position.z /= vert.position.w;
-
-                vert.position.x =
-                    (vert.position.x + 1.0) * state.viewport.width * 0.5 + state.viewport.x;
-                vert.position.y =
-                    (vert.position.y + 1.0) * state.viewport.height * 0.5 + state.viewport.y;
+                vert.position = state.window_coords(vert.position);
             }
 
-            let fb = state.fb.as_mut().unwrap();
-            let texture = &state.textures[state.bound_texture];
+            let fb = &mut state.fb;
 
             fb.draw_triangle(
+                &ops,
                 [tri[0].position, tri[1].position, tri[2].position],
                 |bary| {
-                    if texture.width == 0 || texture.height == 0 {
-                        return bary;
+                    let mut color =
+                        bary[0] * tri[0].color + bary[1] * tri[1].color + bary[2] * tri[2].color;
+                    for &(i, env, texture) in &units {
+                        let tex_coord = bary[0] * tri[0].tex_coords[i]
+                            + bary[1] * tri[1].tex_coords[i]
+                            + bary[2] * tri[2].tex_coords[i];
+                        color = env.apply(texture.base_format, color, texture.sample(tex_coord));
                     }
-                    let uv = bary[0] * tri[0].tex_coord
-                        + bary[1] * tri[1].tex_coord
-                        + bary[2] * tri[2].tex_coord;
-                    let x = (uv[0].rem_euclid(1.0) * texture.width as f32) as usize % texture.width;
-                    let y =
-                        (uv[1].rem_euclid(1.0) * texture.height as f32) as usize % texture.height;
-                    texture.data[x + y * texture.width].xyz()
+                    color
                 },
             );
         }
@@ -609,48 +1377,77 @@ pub extern "system" fn glEnd() {
 
 #[no_mangle]
 pub extern "system" fn glTexCoord2f(s: GLfloat, t: GLfloat) {
-    GL_STATE.with(|state| {
-        let state = &mut *state.borrow_mut();
-        state.tex_coord = Vec4::new(s, t, 0.0, 1.0);
+    trace!(glTexCoord2f, s, t);
+    with_state(|state| {
+        state.texture_units[0].tex_coord = Vec4::new(s, t, 0.0, 1.0);
     })
 }
 
 #[no_mangle]
 pub extern "system" fn glVertex2f(x: GLfloat, y: GLfloat) {
+    trace!(glVertex2f, x, y);
     glVertex4f(x, y, 0.0, 1.0);
 }
 
 #[no_mangle]
 pub extern "system" fn glVertex3f(x: GLfloat, y: GLfloat, z: GLfloat) {
+    trace!(glVertex3f, x, y, z);
     glVertex4f(x, y, z, 1.0);
 }
 
 #[no_mangle]
 pub extern "system" fn glVertex3fv(v: &[GLfloat; 3]) {
+    trace!(glVertex3fv, v);
     glVertex4f(v[0], v[1], v[2], 1.0);
 }
 
 #[no_mangle]
 pub extern "system" fn glVertex4f(x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat) {
-    GL_STATE.with(|state| {
-        let state = &mut *state.borrow_mut();
+    trace!(glVertex4f, x, y, z, w);
+    with_state(|state| {
         state.primitive.vertices.push(Vertex {
             position: Vec4::new(x, y, z, w),
-            tex_coord: state.tex_coord,
+            color: state.color,
+            tex_coords: state.texture_units.each_ref().map(|unit| unit.tex_coord),
         });
     });
 }
 
 #[no_mangle]
-pub extern "system" fn glDrawBuffer(_buf: GLenum) {}
+pub extern "system" fn glDrawBuffer(mode: GLenum) {
+    trace!(glDrawBuffer, mode);
+    with_state(|state| {
+        let double_buffer = state.fb.format.double_buffer;
+        // there are no right or aux buffers, and the back buffer only exists if double buffered
+        state.draw_buffer = match mode {
+            GL_NONE => DrawBuffer::None,
+            GL_FRONT | GL_FRONT_LEFT => DrawBuffer::Front,
+            GL_BACK | GL_BACK_LEFT if double_buffer => DrawBuffer::Back,
+            GL_FRONT_AND_BACK | GL_LEFT if double_buffer => DrawBuffer::FrontAndBack,
+            GL_FRONT_AND_BACK | GL_LEFT => DrawBuffer::Front,
+            _ => return,
+        };
+    });
+}
 
 #[no_mangle]
-pub extern "system" fn glRotatef(angle: GLfloat, x: GLfloat, y: GLfloat, z: GLfloat) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+pub extern "system" fn glReadBuffer(mode: GLenum) {
+    trace!(glReadBuffer, mode);
+    with_state(|state| {
+        let double_buffer = state.fb.format.double_buffer;
+        state.read_buffer = match mode {
+            GL_FRONT | GL_FRONT_LEFT | GL_LEFT => ColorBuffer::Front,
+            GL_BACK | GL_BACK_LEFT if double_buffer => ColorBuffer::Back,
+            _ => return,
+        };
+    });
+}
 
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+#[no_mangle]
+pub extern "system" fn glRotatef(angle: GLfloat, x: GLfloat, y: GLfloat, z: GLfloat) {
+    trace!(glRotatef, angle, x, y, z);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(top) = stack.last_mut() {
             let norm = (x * x + y * y + z * z).sqrt();
             let (x, y, z) = (x / norm, y / norm, z / norm);
@@ -683,11 +1480,9 @@ pub extern "system" fn glRotatef(angle: GLfloat, x: GLfloat, y: GLfloat, z: GLfl
 
 #[no_mangle]
 pub extern "system" fn glTranslatef(x: GLfloat, y: GLfloat, z: GLfloat) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
-
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+    trace!(glTranslatef, x, y, z);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(top) = stack.last_mut() {
             *top *= Mat4::new(
                 [1.0, 0.0, 0.0, x],
@@ -701,11 +1496,9 @@ pub extern "system" fn glTranslatef(x: GLfloat, y: GLfloat, z: GLfloat) {
 
 #[no_mangle]
 pub extern "system" fn glScalef(x: GLfloat, y: GLfloat, z: GLfloat) {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
-
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+    trace!(glScalef, x, y, z);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(top) = stack.last_mut() {
             *top *= Mat4::new(
                 [x, 0.0, 0.0, 0.0],
@@ -718,15 +1511,47 @@ pub extern "system" fn glScalef(x: GLfloat, y: GLfloat, z: GLfloat) {
 }
 
 #[no_mangle]
-pub extern "system" fn glGetFloatv(_pname: GLenum, _params: *mut GLfloat) {}
+pub extern "system" fn glGetFloatv(_pname: GLenum, _params: *mut GLfloat) {
+    trace!(glGetFloatv, _pname);
+}
 
 #[no_mangle]
-pub extern "system" fn glPushMatrix() {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+pub extern "system" fn glGetIntegerv(pname: GLenum, params: *mut GLint) {
+    trace!(glGetIntegerv, pname);
+    with_state(|state| {
+        let value = match pname {
+            GL_MAX_TEXTURE_UNITS_ARB | GL_MAX_TEXTURES_SGIS => MAX_TEXTURE_UNITS as GLint,
+            GL_ACTIVE_TEXTURE_ARB => (GL_TEXTURE0_ARB as usize + state.active_texture) as GLint,
+            GL_CLIENT_ACTIVE_TEXTURE_ARB => {
+                (GL_TEXTURE0_ARB as usize + state.client_active_texture) as GLint
+            }
+            GL_SELECTED_TEXTURE_SGIS => (GL_TEXTURE0_SGIS as usize + state.active_texture) as GLint,
+            GL_DRAW_BUFFER => {
+                (match state.draw_buffer {
+                    DrawBuffer::None => GL_NONE,
+                    DrawBuffer::Front => GL_FRONT,
+                    DrawBuffer::Back => GL_BACK,
+                    DrawBuffer::FrontAndBack => GL_FRONT_AND_BACK,
+                }) as GLint
+            }
+            GL_READ_BUFFER => {
+                (match state.read_buffer {
+                    ColorBuffer::Front => GL_FRONT,
+                    ColorBuffer::Back => GL_BACK,
+                }) as GLint
+            }
+            GL_DOUBLEBUFFER => state.fb.format.double_buffer as GLint,
+            _ => return,
+        };
+        unsafe { *params = value };
+    });
+}
 
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
+#[no_mangle]
+pub extern "system" fn glPushMatrix() {
+    trace!(glPushMatrix);
+    with_state(|state| {
+        let stack = state.matrix_stack();
         if let Some(top) = stack.last() {
             stack.push(*top);
         }
@@ -735,12 +1560,331 @@ pub extern "system" fn glPushMatrix() {
 
 #[no_mangle]
 pub extern "system" fn glPopMatrix() {
-    GL_STATE.with(|state| {
-        let mut state = state.borrow_mut();
+    trace!(glPopMatrix);
+    with_state(|state| {
+        // the last matrix cannot be popped
+        let stack = state.matrix_stack();
+        if stack.len() > 1 {
+            stack.pop();
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glPixelStorei(pname: GLenum, param: GLint) {
+    trace!(glPixelStorei, pname, param);
+    with_state(|state| {
+        let count = param.max(0) as usize;
+        let alignment = matches!(param, 1 | 2 | 4 | 8);
+        match pname {
+            GL_PACK_SWAP_BYTES => state.pack.swap_bytes = param != 0,
+            GL_PACK_LSB_FIRST => state.pack.lsb_first = param != 0,
+            GL_PACK_ROW_LENGTH => state.pack.row_length = count,
+            GL_PACK_SKIP_ROWS => state.pack.skip_rows = count,
+            GL_PACK_SKIP_PIXELS => state.pack.skip_pixels = count,
+            GL_PACK_ALIGNMENT if alignment => state.pack.alignment = count,
+            GL_UNPACK_SWAP_BYTES => state.unpack.swap_bytes = param != 0,
+            GL_UNPACK_LSB_FIRST => state.unpack.lsb_first = param != 0,
+            GL_UNPACK_ROW_LENGTH => state.unpack.row_length = count,
+            GL_UNPACK_SKIP_ROWS => state.unpack.skip_rows = count,
+            GL_UNPACK_SKIP_PIXELS => state.unpack.skip_pixels = count,
+            GL_UNPACK_ALIGNMENT if alignment => state.unpack.alignment = count,
+            _ => {}
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glPixelStoref(pname: GLenum, param: GLfloat) {
+    trace!(glPixelStoref, pname, param);
+    glPixelStorei(pname, param.round() as GLint);
+}
+
+#[no_mangle]
+pub extern "system" fn glReadPixels(
+    x: GLint,
+    y: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    format: GLenum,
+    type_: GLenum,
+    data: *mut GLvoid,
+) {
+    trace!(glReadPixels, x, y, width, height, format, type_);
+    with_state(|state| {
+        let fb = &state.fb;
+
+        let Some(layout) = Layout::new(
+            &state.pack,
+            format,
+            type_,
+            width.max(0) as usize,
+            height.max(0) as usize,
+        ) else {
+            return;
+        };
+        if data.is_null() || layout.size() == 0 {
+            return;
+        }
+        let data = unsafe { std::slice::from_raw_parts_mut(data as *mut u8, layout.size()) };
+
+        for row in 0..layout.height {
+            // both the framebuffer and client memory have their first row at the bottom
+            let fb_y = y + row as GLint;
+            if fb_y < 0 || fb_y >= fb.height as GLint {
+                continue;
+            }
+            for col in 0..layout.width {
+                let fb_x = x + col as GLint;
+                if fb_x < 0 || fb_x >= fb.width as GLint {
+                    continue;
+                }
+                let (fb_x, fb_y) = (fb_x as usize, fb_y as usize);
+
+                let mut dst = &mut data[layout.pixel_offset(col, row)..];
+                match format {
+                    GL_DEPTH_COMPONENT => {
+                        layout.put_normalized(dst, fb.z_buffer[fb_x + fb_y * fb.width]);
+                    }
+                    GL_STENCIL_INDEX => {
+                        let stencil = fb.stencil_at(fb_x + fb_y * fb.width);
+                        layout.put_index(dst, stencil as u32);
+                    }
+                    _ => {
+                        let rgba = fb
+                            .color_at(state.read_buffer, fb_x, fb_y)
+                            .map(|c| c as f32 / 255.0);
+                        let components = layout.color_components(rgba);
+                        for &component in &components[..layout.components] {
+                            layout.put_normalized(dst, component);
+                            dst = &mut dst[layout.element_size..];
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos2i(x: GLint, y: GLint) {
+    trace!(glRasterPos2i, x, y);
+    glRasterPos4f(x as GLfloat, y as GLfloat, 0.0, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos2f(x: GLfloat, y: GLfloat) {
+    trace!(glRasterPos2f, x, y);
+    glRasterPos4f(x, y, 0.0, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos2d(x: GLdouble, y: GLdouble) {
+    trace!(glRasterPos2d, x, y);
+    glRasterPos4f(x as GLfloat, y as GLfloat, 0.0, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos3i(x: GLint, y: GLint, z: GLint) {
+    trace!(glRasterPos3i, x, y, z);
+    glRasterPos4f(x as GLfloat, y as GLfloat, z as GLfloat, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos3f(x: GLfloat, y: GLfloat, z: GLfloat) {
+    trace!(glRasterPos3f, x, y, z);
+    glRasterPos4f(x, y, z, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos3d(x: GLdouble, y: GLdouble, z: GLdouble) {
+    trace!(glRasterPos3d, x, y, z);
+    glRasterPos4f(x as GLfloat, y as GLfloat, z as GLfloat, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos2fv(v: &[GLfloat; 2]) {
+    trace!(glRasterPos2fv, v);
+    glRasterPos4f(v[0], v[1], 0.0, 1.0);
+}
+
+#[no_mangle]
+pub extern "system" fn glRasterPos3fv(v: &[GLfloat; 3]) {
+    trace!(glRasterPos3fv, v);
+    glRasterPos4f(v[0], v[1], v[2], 1.0);
+}
 
-        let index = state.matrix_mode as usize;
-        let stack = &mut state.matrix_stacks[index];
-        stack.pop();
+#[no_mangle]
+pub extern "system" fn glRasterPos4f(x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat) {
+    trace!(glRasterPos4f, x, y, z, w);
+    with_state(|state| {
+        let m = *state.matrix_stacks[MatrixMode::Projection as usize]
+            .last()
+            .unwrap()
+            * *state.matrix_stacks[MatrixMode::ModelView as usize]
+                .last()
+                .unwrap();
+        let clip = m * Vec4::new(x, y, z, w);
+
+        let valid = (0..3).all(|i| -clip.w <= clip[i] && clip[i] <= clip.w);
+        state.raster.valid = valid;
+        if valid {
+            state.raster.position = state.window_coords(clip);
+            state.raster.color = state.color;
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glPixelZoom(xfactor: GLfloat, yfactor: GLfloat) {
+    trace!(glPixelZoom, xfactor, yfactor);
+    with_state(|state| {
+        state.pixel_zoom = (xfactor, yfactor);
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glDrawPixels(
+    width: GLsizei,
+    height: GLsizei,
+    format: GLenum,
+    type_: GLenum,
+    pixels: *const GLvoid,
+) {
+    trace!(glDrawPixels, width, height, format, type_, unsafe {
+        trace::slice(
+            pixels as *const u8,
+            unpack_size(format, type_, width, height),
+        )
+    });
+    with_state(|state| {
+        let Some(layout) = Layout::new(
+            &state.unpack,
+            format,
+            type_,
+            width.max(0) as usize,
+            height.max(0) as usize,
+        ) else {
+            return;
+        };
+        if pixels.is_null() || layout.size() == 0 {
+            return;
+        }
+        let data = unsafe { std::slice::from_raw_parts(pixels as *const u8, layout.size()) };
+
+        let sources = (0..layout.height)
+            .flat_map(|y| (0..layout.width).map(move |x| (x, y)))
+            .map(|(x, y)| &data[layout.pixel_offset(x, y)..]);
+        let rect = match format {
+            GL_DEPTH_COMPONENT => {
+                PixelRect::Depth(sources.map(|src| layout.get_normalized(src)).collect())
+            }
+            GL_STENCIL_INDEX => {
+                PixelRect::Stencil(sources.map(|src| layout.get_index(src)).collect())
+            }
+            _ => PixelRect::Color(sources.map(|src| layout.get_color(src)).collect()),
+        };
+
+        state.draw_pixel_rect(layout.width, layout.height, &rect);
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glCopyPixels(
+    x: GLint,
+    y: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    type_: GLenum,
+) {
+    trace!(glCopyPixels, x, y, width, height, type_);
+    with_state(|state| {
+        let fb = &state.fb;
+
+        let (width, height) = (width.max(0) as usize, height.max(0) as usize);
+        // with nothing to read from there is nothing to clamp to, and nothing could be drawn
+        if width == 0 || height == 0 || fb.width == 0 || fb.height == 0 {
+            return;
+        }
+        // pixels outside the framebuffer are undefined, so clamp to its edges
+        let sources = (0..height)
+            .flat_map(|row| (0..width).map(move |col| (col, row)))
+            .map(|(col, row)| {
+                let fb_x = (x + col as GLint).clamp(0, fb.width as GLint - 1) as usize;
+                let fb_y = (y + row as GLint).clamp(0, fb.height as GLint - 1) as usize;
+                fb_x + fb_y * fb.width
+            });
+        let rect = match type_ {
+            GL_COLOR => PixelRect::Color(
+                sources
+                    .map(|i| {
+                        fb.color_at(state.read_buffer, i % fb.width, i / fb.width)
+                            .map(|c| c as f32 / 255.0)
+                    })
+                    .collect(),
+            ),
+            GL_DEPTH => PixelRect::Depth(sources.map(|i| fb.z_buffer[i]).collect()),
+            GL_STENCIL => PixelRect::Stencil(sources.map(|i| fb.stencil_at(i) as u32).collect()),
+            _ => return,
+        };
+
+        state.draw_pixel_rect(width, height, &rect);
+    });
+}
+
+#[no_mangle]
+pub extern "system" fn glBitmap(
+    width: GLsizei,
+    height: GLsizei,
+    xorig: GLfloat,
+    yorig: GLfloat,
+    xmove: GLfloat,
+    ymove: GLfloat,
+    bitmap: *const GLubyte,
+) {
+    trace!(
+        glBitmap,
+        width,
+        height,
+        xorig,
+        yorig,
+        xmove,
+        ymove,
+        unsafe { trace::slice(bitmap, unpack_bitmap_size(width, height)) },
+    );
+    with_state(|state| {
+        if !state.raster.valid {
+            return;
+        }
+
+        let layout =
+            BitmapLayout::new(&state.unpack, width.max(0) as usize, height.max(0) as usize);
+        if !bitmap.is_null() && layout.size() > 0 {
+            let data = unsafe { std::slice::from_raw_parts(bitmap, layout.size()) };
+            let ops = state.fragment_ops();
+            let raster = state.raster;
+            let color = raster.color.as_array().map(to_unorm8);
+            let fb = &mut state.fb;
+
+            let x0 = (raster.position.x - xorig).floor() as i32;
+            let y0 = (raster.position.y - yorig).floor() as i32;
+            for y in 0..layout.height {
+                for x in 0..layout.width {
+                    if layout.bit(data, x, y) {
+                        fb.draw_fragment(
+                            &ops,
+                            x0 + x as i32,
+                            y0 + y as i32,
+                            raster.position.z,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+
+        state.raster.position.x += xmove;
+        state.raster.position.y += ymove;
     });
 }
 
@@ -756,4 +1900,21 @@ pub extern "system" fn glTexSubImage2D(
     _type: GLenum,
     _pixels: *const c_void,
 ) {
+    trace!(
+        glTexSubImage2D,
+        _target,
+        _level,
+        _xoffset,
+        _yoffset,
+        _width,
+        _height,
+        _format,
+        _type,
+        unsafe {
+            trace::slice(
+                _pixels as *const u8,
+                unpack_size(_format, _type, _width, _height),
+            )
+        },
+    );
 }
@@ -0,0 +1,27 @@
+//! Objects that can be shared between contexts with wglShareLists.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::texture::Textures;
+
+/// The shareable objects of one or more contexts. Display lists would live here as well, but
+/// minigl does not implement them.
+#[derive(Default)]
+pub struct Namespace {
+    pub textures: Textures,
+}
+
+/// A reference to a namespace, which is freed once the last context using it is deleted.
+#[derive(Clone, Default)]
+pub struct SharedNamespace(Arc<Mutex<Namespace>>);
+
+impl SharedNamespace {
+    pub fn lock(&self) -> MutexGuard<'_, Namespace> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[cfg(windows)]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
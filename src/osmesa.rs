@@ -0,0 +1,421 @@
+//! An OSMesa compatible front end for rendering into caller memory with no window system.
+//!
+//! Contexts are single buffered and render into their own framebuffer as usual. The color buffer
+//! is converted into the caller's buffer whenever the front buffer is presented, which happens on
+//! glFlush and glFinish like for any single buffered context, and also in OSMesaGetColorBuffer.
+
+use std::{collections::BTreeMap, ffi::c_void, sync::Mutex};
+
+use crate::{
+    context::{self, Context, Handle},
+    format::{self, FormatRequest},
+    presenter::Presenter,
+    procs, GLState, GLboolean, GLenum, GLint, GLsizei, GL_FALSE, GL_RGB, GL_RGBA, GL_UNSIGNED_BYTE,
+    GL_UNSIGNED_SHORT_5_6_5,
+};
+
+type OSMesaContext = *mut c_void;
+
+const OSMESA_BGRA: GLenum = 0x1;
+const OSMESA_ARGB: GLenum = 0x2;
+const OSMESA_BGR: GLenum = 0x4;
+const OSMESA_RGB_565: GLenum = 0x5;
+
+const OSMESA_ROW_LENGTH: GLint = 0x10;
+const OSMESA_Y_UP: GLint = 0x11;
+
+const GL_TRUE: GLboolean = 1;
+
+/// The largest buffer OSMesaMakeCurrent accepts in either dimension.
+const MAX_SIZE: GLsizei = 16384;
+
+/// What each context renders into, besides its framebuffer.
+struct Binding {
+    format: GLenum,
+    /// The address, width and height of the caller's buffer, once one has been bound.
+    buffer: Option<(usize, usize, usize)>,
+    /// Pixels per row in the caller's buffer, or 0 for the buffer's width.
+    row_length: usize,
+    y_up: bool,
+    /// The depth buffer converted to integers for OSMesaGetDepthBuffer.
+    depth: Vec<u8>,
+}
+
+static BINDINGS: Mutex<BTreeMap<Handle, Binding>> = Mutex::new(BTreeMap::new());
+
+fn with_bindings<R>(f: impl FnOnce(&mut BTreeMap<Handle, Binding>) -> R) -> R {
+    f(&mut BINDINGS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+fn handle(context: OSMesaContext) -> Handle {
+    context as usize as Handle
+}
+
+/// Returns the size in bytes of one pixel of an OSMesa format.
+fn bytes_per_pixel(format: GLenum) -> Option<usize> {
+    match format {
+        GL_RGBA | OSMESA_BGRA | OSMESA_ARGB => Some(4),
+        GL_RGB | OSMESA_BGR => Some(3),
+        OSMESA_RGB_565 => Some(2),
+        _ => None,
+    }
+}
+
+/// Stores `value` through an out parameter, unless it is null.
+unsafe fn write<T>(out: *mut T, value: T) {
+    if !out.is_null() {
+        *out = value;
+    }
+}
+
+/// Converts the front buffer into the caller's buffer.
+struct BufferPresenter {
+    address: usize,
+    width: usize,
+    height: usize,
+    format: GLenum,
+    row_length: usize,
+    y_up: bool,
+}
+
+impl BufferPresenter {
+    fn new(binding: &Binding) -> Option<Self> {
+        let (address, width, height) = binding.buffer?;
+        Some(Self {
+            address,
+            width,
+            height,
+            format: binding.format,
+            row_length: if binding.row_length > 0 {
+                binding.row_length
+            } else {
+                width
+            },
+            y_up: binding.y_up,
+        })
+    }
+}
+
+impl Presenter for BufferPresenter {
+    fn size(&self) -> Option<(usize, usize)> {
+        Some((self.width, self.height))
+    }
+
+    fn present(&mut self, pixels: &[u8], width: usize, height: usize) {
+        let bytes_per_pixel = bytes_per_pixel(self.format).unwrap();
+        let stride = self.row_length * bytes_per_pixel;
+        let size = (self.height - 1) * stride + self.width * bytes_per_pixel;
+        let buffer = unsafe { std::slice::from_raw_parts_mut(self.address as *mut u8, size) };
+
+        for y in 0..height.min(self.height) {
+            let row = if self.y_up { y } else { self.height - 1 - y };
+            let dst = &mut buffer[row * stride..];
+            let src = &pixels[y * width * 4..(y + 1) * width * 4];
+            for (x, bgra) in src.chunks_exact(4).take(self.width).enumerate() {
+                let [b, g, r, a] = [bgra[0], bgra[1], bgra[2], bgra[3]];
+                let dst = &mut dst[x * bytes_per_pixel..(x + 1) * bytes_per_pixel];
+                match self.format {
+                    GL_RGBA => dst.copy_from_slice(&[r, g, b, a]),
+                    OSMESA_BGRA => dst.copy_from_slice(&[b, g, r, a]),
+                    OSMESA_ARGB => dst.copy_from_slice(&[a, r, g, b]),
+                    GL_RGB => dst.copy_from_slice(&[r, g, b]),
+                    OSMESA_BGR => dst.copy_from_slice(&[b, g, r]),
+                    _ => {
+                        let rgb565 = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+                        dst.copy_from_slice(&rgb565.to_ne_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn OSMesaCreateContext(
+    format: GLenum,
+    sharelist: OSMesaContext,
+) -> OSMesaContext {
+    OSMesaCreateContextExt(format, 16, 8, 0, sharelist)
+}
+
+#[no_mangle]
+pub extern "system" fn OSMesaCreateContextExt(
+    format: GLenum,
+    depth_bits: GLint,
+    stencil_bits: GLint,
+    _accum_bits: GLint,
+    sharelist: OSMesaContext,
+) -> OSMesaContext {
+    if bytes_per_pixel(format).is_none() {
+        return std::ptr::null_mut();
+    }
+    let alpha_bits = if matches!(format, GL_RGBA | OSMESA_BGRA | OSMESA_ARGB) {
+        8
+    } else {
+        0
+    };
+    let request = FormatRequest {
+        alpha_bits,
+        depth_bits: Some(depth_bits.clamp(0, 32) as u8),
+        stencil_bits: stencil_bits.clamp(0, 8) as u8,
+        double_buffer: Some(false),
+        ..Default::default()
+    };
+    let format_index = format::choose(&request);
+    let mut pixel_format = format::supported().nth(format_index).unwrap();
+    // every supported format has a depth buffer, but OSMesa applications can ask for none
+    if depth_bits <= 0 {
+        pixel_format.depth_bits = 0;
+    }
+
+    let mut state = GLState::new(0, 0, pixel_format);
+    if !sharelist.is_null() {
+        match context::with_context(handle(sharelist), |context| context.state.namespace.clone()) {
+            Some(namespace) => state.namespace = namespace,
+            None => return std::ptr::null_mut(),
+        }
+    }
+
+    let handle = context::create(Context::new(state));
+    with_bindings(|bindings| {
+        bindings.insert(
+            handle,
+            Binding {
+                format,
+                buffer: None,
+                row_length: 0,
+                y_up: true,
+                depth: Vec::new(),
+            },
+        )
+    });
+    handle as usize as OSMesaContext
+}
+
+#[no_mangle]
+pub extern "system" fn OSMesaDestroyContext(context: OSMesaContext) {
+    let handle = handle(context);
+    if context::delete(handle) {
+        with_bindings(|bindings| bindings.remove(&handle));
+    }
+}
+
+/// Binds `context` to a `width` x `height` buffer in the context's format and makes it current.
+/// The buffer's existing contents are not read, so the framebuffer starts out undefined.
+#[no_mangle]
+pub extern "system" fn OSMesaMakeCurrent(
+    context: OSMesaContext,
+    buffer: *mut c_void,
+    type_: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+) -> GLboolean {
+    let handle = handle(context);
+    if buffer.is_null() || !(1..=MAX_SIZE).contains(&width) || !(1..=MAX_SIZE).contains(&height) {
+        return GL_FALSE;
+    }
+
+    let presenter = with_bindings(|bindings| {
+        let binding = bindings.get_mut(&handle)?;
+        let expected_type = match binding.format {
+            OSMESA_RGB_565 => GL_UNSIGNED_SHORT_5_6_5,
+            _ => GL_UNSIGNED_BYTE,
+        };
+        if type_ != expected_type {
+            return None;
+        }
+        let previous = binding
+            .buffer
+            .replace((buffer as usize, width as usize, height as usize));
+        Some((BufferPresenter::new(binding)?, previous))
+    });
+    let Some((presenter, previous)) = presenter else {
+        return GL_FALSE;
+    };
+
+    if !context::make_current(handle) {
+        // the context is still bound to its old buffer, since it is current somewhere else
+        with_bindings(|bindings| {
+            if let Some(binding) = bindings.get_mut(&handle) {
+                binding.buffer = previous;
+            }
+        });
+        return GL_FALSE;
+    }
+    context::with_current(|context| context.set_presenter(Box::new(presenter)));
+    GL_TRUE
+}
+
+#[no_mangle]
+pub extern "system" fn OSMesaGetCurrentContext() -> OSMesaContext {
+    context::current().unwrap_or(0) as usize as OSMesaContext
+}
+
+/// Sets how the current context lays out its color buffer in the caller's buffer.
+#[no_mangle]
+pub extern "system" fn OSMesaPixelStore(pname: GLint, value: GLint) {
+    let Some(handle) = context::current() else {
+        return;
+    };
+    let presenter = with_bindings(|bindings| {
+        let binding = bindings.get_mut(&handle)?;
+        match pname {
+            OSMESA_ROW_LENGTH if value >= 0 => binding.row_length = value as usize,
+            OSMESA_Y_UP => binding.y_up = value != 0,
+            _ => return None,
+        }
+        BufferPresenter::new(binding)
+    });
+    if let Some(presenter) = presenter {
+        context::with_current(|context| context.set_presenter(Box::new(presenter)));
+    }
+}
+
+/// Returns the caller's buffer bound to `context`, after bringing it up to date with the
+/// framebuffer.
+#[no_mangle]
+pub extern "system" fn OSMesaGetColorBuffer(
+    context: OSMesaContext,
+    width: *mut GLint,
+    height: *mut GLint,
+    format: *mut GLint,
+    buffer: *mut *mut c_void,
+) -> GLboolean {
+    let handle = handle(context);
+    let Some((binding_format, (address, buffer_width, buffer_height))) =
+        with_bindings(|bindings| {
+            let binding = bindings.get(&handle)?;
+            Some((binding.format, binding.buffer?))
+        })
+    else {
+        return GL_FALSE;
+    };
+    context::with_context(handle, |context| context.flush());
+
+    // callers pass null for the values they do not need
+    unsafe {
+        write(width, buffer_width as GLint);
+        write(height, buffer_height as GLint);
+        write(format, binding_format as GLint);
+        write(buffer, address as *mut c_void);
+    }
+    GL_TRUE
+}
+
+/// Returns a copy of `context`'s depth buffer as 16 bit integers for 16 bit formats or 32 bit
+/// integers otherwise, scaled to the format's depth bits. The copy stays valid until the next call
+/// for the same context. Fails, setting everything to 0, if the context has no depth buffer.
+#[no_mangle]
+pub extern "system" fn OSMesaGetDepthBuffer(
+    context: OSMesaContext,
+    width: *mut GLint,
+    height: *mut GLint,
+    bytes_per_value: *mut GLint,
+    buffer: *mut *mut c_void,
+) -> GLboolean {
+    let handle = handle(context);
+    let depth = context::with_context(handle, |context| {
+        let fb = &context.state.fb;
+        let bits = fb.format.depth_bits;
+        if bits == 0 {
+            return None;
+        }
+        let max = ((1u64 << bits) - 1) as f64;
+        let scale = |z: f32| (z as f64 * max).round();
+        let (bytes, depth): (usize, Vec<u8>) = if bits == 16 {
+            let values = fb.z_buffer.iter().map(|&z| scale(z) as u16);
+            (2, values.flat_map(u16::to_ne_bytes).collect())
+        } else {
+            let values = fb.z_buffer.iter().map(|&z| scale(z) as u32);
+            (4, values.flat_map(u32::to_ne_bytes).collect())
+        };
+        Some((fb.width, fb.height, bytes, depth))
+    });
+    let Some(Some((fb_width, fb_height, bytes, depth))) = depth else {
+        unsafe {
+            write(width, 0);
+            write(height, 0);
+            write(bytes_per_value, 0);
+            write(buffer, std::ptr::null_mut());
+        }
+        return GL_FALSE;
+    };
+
+    with_bindings(|bindings| {
+        let Some(binding) = bindings.get_mut(&handle) else {
+            return GL_FALSE;
+        };
+        binding.depth = depth;
+        unsafe {
+            write(width, fb_width as GLint);
+            write(height, fb_height as GLint);
+            write(bytes_per_value, bytes as GLint);
+            write(buffer, binding.depth.as_mut_ptr() as *mut c_void);
+        }
+        GL_TRUE
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn OSMesaGetProcAddress(name: *const std::ffi::c_char) -> *const c_void {
+    if name.is_null() {
+        return std::ptr::null();
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name) };
+    procs::lookup(name.to_bytes()).unwrap_or(std::ptr::null())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PrimitiveMode, GL_COLOR_BUFFER_BIT, GL_DEPTH_TEST, GL_NEVER};
+
+    /// Returns what OSMesaGetDepthBuffer reports for `context`.
+    fn depth_buffer(context: OSMesaContext) -> (GLboolean, [GLint; 3], bool) {
+        let (mut width, mut height, mut bytes) = (-1, -1, -1);
+        let mut buffer = std::ptr::dangling_mut();
+        let result =
+            OSMesaGetDepthBuffer(context, &mut width, &mut height, &mut bytes, &mut buffer);
+        (result, [width, height, bytes], buffer.is_null())
+    }
+
+    #[test]
+    fn contexts_can_have_no_depth_buffer() {
+        let context = OSMesaCreateContextExt(GL_RGBA, 0, 0, 0, std::ptr::null_mut());
+        let mut pixels = [0u8; 4 * 4 * 4];
+        let buffer = pixels.as_mut_ptr().cast();
+        assert_eq!(
+            OSMesaMakeCurrent(context, buffer, GL_UNSIGNED_BYTE, 4, 4),
+            GL_TRUE
+        );
+        assert_eq!(depth_buffer(context), (GL_FALSE, [0; 3], true));
+
+        // the depth test passes without a depth buffer to test against
+        crate::glEnable(GL_DEPTH_TEST);
+        crate::glDepthFunc(GL_NEVER);
+        crate::glClearColor(0.0, 0.0, 1.0, 1.0);
+        crate::glClear(GL_COLOR_BUFFER_BIT);
+        crate::glColor3f(1.0, 0.0, 0.0);
+        crate::glBegin(PrimitiveMode::Quads);
+        for (x, y) in [(-0.9, -0.9), (0.9, -0.9), (0.9, 0.9), (-0.9, 0.9)] {
+            crate::glVertex2f(x, y);
+        }
+        crate::glEnd();
+        crate::glFinish();
+        assert_eq!(pixels[(2 * 4 + 2) * 4..][..4], [255, 0, 0, 255]);
+
+        context::make_current(0);
+        OSMesaDestroyContext(context);
+    }
+
+    #[test]
+    fn depth_buffers_have_the_requested_bits() {
+        let context = OSMesaCreateContextExt(GL_RGBA, 16, 0, 0, std::ptr::null_mut());
+        let mut pixels = [0u8; 2 * 2 * 4];
+        let buffer = pixels.as_mut_ptr().cast();
+        OSMesaMakeCurrent(context, buffer, GL_UNSIGNED_BYTE, 2, 2);
+        assert_eq!(depth_buffer(context), (GL_TRUE, [2, 2, 2], false));
+        context::make_current(0);
+        OSMesaDestroyContext(context);
+    }
+}
@@ -0,0 +1,63 @@
+//! Frame pacing for swap intervals.
+//!
+//! A software renderer has no vertical blank to wait for, so swaps are instead spaced out to match
+//! a fixed refresh rate, which can be set with the `MINIGL_REFRESH_RATE` environment variable.
+
+use std::{
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_REFRESH_RATE: f64 = 60.0;
+
+/// The refresh rate of the imaginary display, in Hz.
+fn refresh_rate() -> f64 {
+    static RATE: OnceLock<f64> = OnceLock::new();
+    *RATE.get_or_init(|| {
+        std::env::var("MINIGL_REFRESH_RATE")
+            .ok()
+            .and_then(|rate| rate.trim().parse::<f64>().ok())
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .unwrap_or(DEFAULT_REFRESH_RATE)
+    })
+}
+
+pub struct FramePacer {
+    /// The number of refresh periods each frame is shown for. 0 disables pacing.
+    pub interval: u32,
+    /// When the last frame was presented, or should have been if it was late.
+    last_frame: Option<Instant>,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self {
+            interval: 1,
+            last_frame: None,
+        }
+    }
+}
+
+impl FramePacer {
+    /// Sleeps until it is time to present the next frame.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if self.interval == 0 {
+            self.last_frame = Some(now);
+            return;
+        }
+
+        let period = Duration::from_secs_f64(self.interval as f64 / refresh_rate());
+        let due = self.last_frame.map_or(now, |last| last + period);
+        if due > now {
+            thread::sleep(due - now);
+            // scheduling from when the frame was due rather than when we woke up keeps oversleeping
+            // from lowering the frame rate
+            self.last_frame = Some(due);
+        } else {
+            // a slow frame should not make the following ones rush to catch up
+            self.last_frame = Some(now);
+        }
+    }
+}
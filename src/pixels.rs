@@ -0,0 +1,432 @@
+//! Conversions between client memory and framebuffer values for pixel transfer operations.
+
+use crate::{
+    GLenum, GL_ALPHA, GL_BGR, GL_BGRA, GL_BLUE, GL_BYTE, GL_DEPTH_COMPONENT, GL_FLOAT, GL_GREEN,
+    GL_INT, GL_LUMINANCE, GL_LUMINANCE_ALPHA, GL_RED, GL_RGB, GL_RGBA, GL_SHORT, GL_STENCIL_INDEX,
+    GL_UNSIGNED_BYTE, GL_UNSIGNED_INT, GL_UNSIGNED_SHORT,
+};
+
+/// Pack or unpack parameters set with glPixelStore.
+#[derive(Copy, Clone, Debug)]
+pub struct PixelStore {
+    pub swap_bytes: bool,
+    pub lsb_first: bool,
+    pub row_length: usize,
+    pub skip_rows: usize,
+    pub skip_pixels: usize,
+    pub alignment: usize,
+}
+
+impl Default for PixelStore {
+    fn default() -> Self {
+        Self {
+            swap_bytes: false,
+            lsb_first: false,
+            row_length: 0,
+            skip_rows: 0,
+            skip_pixels: 0,
+            alignment: 4,
+        }
+    }
+}
+
+/// Describes how a `width` x `height` image of `format`/`type_` is laid out in client memory.
+#[derive(Copy, Clone, Debug)]
+pub struct Layout {
+    pub format: GLenum,
+    pub type_: GLenum,
+    pub components: usize,
+    pub element_size: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Offset of the first pixel of the first row, in bytes.
+    pub offset: usize,
+    /// Distance between the starts of consecutive rows, in bytes.
+    pub stride: usize,
+    pub swap_bytes: bool,
+}
+
+impl Layout {
+    /// Returns `None` if `format` or `type_` is not supported.
+    pub fn new(
+        store: &PixelStore,
+        format: GLenum,
+        type_: GLenum,
+        width: usize,
+        height: usize,
+    ) -> Option<Self> {
+        let components = match format {
+            GL_RED | GL_GREEN | GL_BLUE | GL_ALPHA | GL_LUMINANCE => 1,
+            GL_DEPTH_COMPONENT | GL_STENCIL_INDEX => 1,
+            GL_LUMINANCE_ALPHA => 2,
+            GL_RGB | GL_BGR => 3,
+            GL_RGBA | GL_BGRA => 4,
+            _ => return None,
+        };
+        let element_size = match type_ {
+            GL_UNSIGNED_BYTE | GL_BYTE => 1,
+            GL_UNSIGNED_SHORT | GL_SHORT => 2,
+            GL_UNSIGNED_INT | GL_INT | GL_FLOAT => 4,
+            _ => return None,
+        };
+
+        let row_length = if store.row_length > 0 {
+            store.row_length
+        } else {
+            width
+        };
+        let row_bytes = components * element_size * row_length;
+        let alignment = store.alignment.max(1);
+        let stride = if element_size < alignment {
+            row_bytes.div_ceil(alignment) * alignment
+        } else {
+            row_bytes
+        };
+
+        Some(Self {
+            format,
+            type_,
+            components,
+            element_size,
+            width,
+            height,
+            offset: store.skip_pixels * components * element_size + store.skip_rows * stride,
+            stride,
+            swap_bytes: store.swap_bytes && element_size > 1,
+        })
+    }
+
+    /// Total number of bytes touched in client memory.
+    pub fn size(&self) -> usize {
+        if self.width == 0 || self.height == 0 {
+            return 0;
+        }
+        self.offset
+            + (self.height - 1) * self.stride
+            + self.width * self.components * self.element_size
+    }
+
+    pub fn pixel_offset(&self, x: usize, y: usize) -> usize {
+        self.offset + y * self.stride + x * self.components * self.element_size
+    }
+
+    /// Converts an RGBA color into the components of this layout's format, in client order.
+    pub fn color_components(&self, rgba: [f32; 4]) -> [f32; 4] {
+        let [r, g, b, a] = rgba;
+        match self.format {
+            GL_RED => [r, 0.0, 0.0, 0.0],
+            GL_GREEN => [g, 0.0, 0.0, 0.0],
+            GL_BLUE => [b, 0.0, 0.0, 0.0],
+            GL_ALPHA => [a, 0.0, 0.0, 0.0],
+            GL_LUMINANCE => [(r + g + b).min(1.0), 0.0, 0.0, 0.0],
+            GL_LUMINANCE_ALPHA => [(r + g + b).min(1.0), a, 0.0, 0.0],
+            GL_RGB => [r, g, b, 0.0],
+            GL_BGR => [b, g, r, 0.0],
+            GL_BGRA => [b, g, r, a],
+            _ => [r, g, b, a],
+        }
+    }
+
+    /// Converts the components of this layout's format, in client order, into an RGBA color.
+    pub fn rgba_from_components(&self, components: [f32; 4]) -> [f32; 4] {
+        let [c0, c1, c2, c3] = components;
+        match self.format {
+            GL_RED => [c0, 0.0, 0.0, 1.0],
+            GL_GREEN => [0.0, c0, 0.0, 1.0],
+            GL_BLUE => [0.0, 0.0, c0, 1.0],
+            GL_ALPHA => [0.0, 0.0, 0.0, c0],
+            GL_LUMINANCE => [c0, c0, c0, 1.0],
+            GL_LUMINANCE_ALPHA => [c0, c0, c0, c1],
+            GL_RGB => [c0, c1, c2, 1.0],
+            GL_BGR => [c2, c1, c0, 1.0],
+            GL_BGRA => [c2, c1, c0, c3],
+            _ => [c0, c1, c2, c3],
+        }
+    }
+
+    /// Reads the color of the pixel starting at `src` as RGBA.
+    pub fn get_color(&self, src: &[u8]) -> [f32; 4] {
+        let mut components = [0.0; 4];
+        for (i, component) in components.iter_mut().take(self.components).enumerate() {
+            *component = self.get_normalized(&src[i * self.element_size..]);
+        }
+        self.rgba_from_components(components)
+    }
+
+    /// Reads one element of this layout's type as a normalized value.
+    pub fn get_normalized(&self, src: &[u8]) -> f32 {
+        let bytes = self.get_bytes(src);
+        let value = match self.type_ {
+            GL_UNSIGNED_BYTE => src[0] as f64 / 255.0,
+            GL_BYTE => src[0] as i8 as f64 / 127.0,
+            GL_UNSIGNED_SHORT => u16::from_ne_bytes([bytes[0], bytes[1]]) as f64 / 65535.0,
+            GL_SHORT => i16::from_ne_bytes([bytes[0], bytes[1]]) as f64 / 32767.0,
+            GL_UNSIGNED_INT => u32::from_ne_bytes(bytes) as f64 / u32::MAX as f64,
+            GL_INT => i32::from_ne_bytes(bytes) as f64 / i32::MAX as f64,
+            GL_FLOAT => f32::from_ne_bytes(bytes) as f64,
+            _ => unreachable!(),
+        };
+        value.max(-1.0) as f32
+    }
+
+    /// Reads one element of this layout's type as an index value.
+    pub fn get_index(&self, src: &[u8]) -> u32 {
+        let bytes = self.get_bytes(src);
+        match self.type_ {
+            GL_UNSIGNED_BYTE | GL_BYTE => src[0] as u32,
+            GL_UNSIGNED_SHORT | GL_SHORT => u16::from_ne_bytes([bytes[0], bytes[1]]) as u32,
+            GL_UNSIGNED_INT | GL_INT => u32::from_ne_bytes(bytes),
+            GL_FLOAT => f32::from_ne_bytes(bytes) as u32,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the first `element_size` bytes of `src` in native order, padded to four bytes.
+    fn get_bytes(&self, src: &[u8]) -> [u8; 4] {
+        let mut bytes = [0; 4];
+        bytes[..self.element_size].copy_from_slice(&src[..self.element_size]);
+        if self.swap_bytes {
+            bytes[..self.element_size].reverse();
+        }
+        bytes
+    }
+
+    /// Writes a normalized value in [0, 1] as one element of this layout's type.
+    pub fn put_normalized(&self, dst: &mut [u8], value: f32) {
+        let value = value.clamp(0.0, 1.0) as f64;
+        match self.type_ {
+            GL_UNSIGNED_BYTE => dst[0] = (value * 255.0).round() as u8,
+            GL_BYTE => dst[0] = (value * 127.0).round() as i8 as u8,
+            GL_UNSIGNED_SHORT => {
+                self.put_bytes(dst, &((value * 65535.0).round() as u16).to_ne_bytes())
+            }
+            GL_SHORT => self.put_bytes(dst, &((value * 32767.0).round() as i16).to_ne_bytes()),
+            GL_UNSIGNED_INT => self.put_bytes(
+                dst,
+                &((value * u32::MAX as f64).round() as u32).to_ne_bytes(),
+            ),
+            GL_INT => self.put_bytes(
+                dst,
+                &((value * i32::MAX as f64).round() as i32).to_ne_bytes(),
+            ),
+            GL_FLOAT => self.put_bytes(dst, &(value as f32).to_ne_bytes()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes an index value (such as a stencil value) as one element of this layout's type.
+    pub fn put_index(&self, dst: &mut [u8], value: u32) {
+        match self.type_ {
+            GL_UNSIGNED_BYTE | GL_BYTE => dst[0] = value as u8,
+            GL_UNSIGNED_SHORT | GL_SHORT => self.put_bytes(dst, &(value as u16).to_ne_bytes()),
+            GL_UNSIGNED_INT | GL_INT => self.put_bytes(dst, &value.to_ne_bytes()),
+            GL_FLOAT => self.put_bytes(dst, &(value as f32).to_ne_bytes()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn put_bytes(&self, dst: &mut [u8], bytes: &[u8]) {
+        dst[..bytes.len()].copy_from_slice(bytes);
+        if self.swap_bytes {
+            dst[..bytes.len()].reverse();
+        }
+    }
+}
+
+/// Describes how a glBitmap image is laid out in client memory.
+#[derive(Copy, Clone, Debug)]
+pub struct BitmapLayout {
+    pub width: usize,
+    pub height: usize,
+    /// Offset of the first pixel of the first row, in bits.
+    pub offset: usize,
+    /// Distance between the starts of consecutive rows, in bytes.
+    pub stride: usize,
+    pub lsb_first: bool,
+}
+
+impl BitmapLayout {
+    pub fn new(store: &PixelStore, width: usize, height: usize) -> Self {
+        let row_length = if store.row_length > 0 {
+            store.row_length
+        } else {
+            width
+        };
+        let alignment = store.alignment.max(1);
+        let stride = row_length.div_ceil(8 * alignment) * alignment;
+        Self {
+            width,
+            height,
+            offset: store.skip_pixels + store.skip_rows * stride * 8,
+            stride,
+            lsb_first: store.lsb_first,
+        }
+    }
+
+    /// Total number of bytes touched in client memory.
+    pub fn size(&self) -> usize {
+        if self.width == 0 || self.height == 0 {
+            return 0;
+        }
+        (self.offset + (self.height - 1) * self.stride * 8 + self.width).div_ceil(8)
+    }
+
+    pub fn bit(&self, data: &[u8], x: usize, y: usize) -> bool {
+        let bit = self.offset + y * self.stride * 8 + x;
+        let shift = if self.lsb_first { bit % 8 } else { 7 - bit % 8 };
+        data[bit / 8] >> shift & 1 != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(alignment: usize) -> PixelStore {
+        PixelStore {
+            alignment,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_alignment() {
+        let layout = |alignment, width| {
+            Layout::new(&store(alignment), GL_RGB, GL_UNSIGNED_BYTE, width, 3).unwrap()
+        };
+        assert_eq!(layout(4, 5).stride, 16);
+        assert_eq!(layout(1, 5).stride, 15);
+        assert_eq!(layout(8, 5).stride, 16);
+        assert_eq!(layout(4, 4).stride, 12);
+        // the last row is not padded
+        assert_eq!(layout(4, 5).size(), 2 * 16 + 15);
+    }
+
+    #[test]
+    fn alignment_smaller_than_elements_is_ignored() {
+        let layout = Layout::new(&store(4), GL_RGB, GL_FLOAT, 1, 2).unwrap();
+        assert_eq!(layout.stride, 12);
+        let layout = Layout::new(&store(8), GL_RGB, GL_UNSIGNED_SHORT, 1, 2).unwrap();
+        assert_eq!(layout.stride, 8);
+    }
+
+    #[test]
+    fn row_length_and_skips_move_the_first_pixel() {
+        let store = PixelStore {
+            row_length: 10,
+            skip_rows: 2,
+            skip_pixels: 3,
+            ..store(4)
+        };
+        let layout = Layout::new(&store, GL_RGBA, GL_UNSIGNED_BYTE, 4, 2).unwrap();
+        assert_eq!(layout.stride, 40);
+        assert_eq!(layout.offset, 2 * 40 + 3 * 4);
+        assert_eq!(layout.pixel_offset(1, 1), layout.offset + 40 + 4);
+        assert_eq!(layout.size(), layout.offset + 40 + 16);
+    }
+
+    #[test]
+    fn empty_images_touch_no_memory() {
+        let store = PixelStore {
+            skip_rows: 5,
+            ..store(4)
+        };
+        let layout = Layout::new(&store, GL_RGBA, GL_UNSIGNED_BYTE, 0, 4).unwrap();
+        assert_eq!(layout.size(), 0);
+    }
+
+    #[test]
+    fn unsupported_formats_and_types_are_rejected() {
+        assert!(Layout::new(&store(4), 0x1234, GL_UNSIGNED_BYTE, 1, 1).is_none());
+        assert!(Layout::new(&store(4), GL_RGBA, 0x1234, 1, 1).is_none());
+    }
+
+    #[test]
+    fn stencil_indices_can_be_floats() {
+        let layout = Layout::new(&store(4), GL_STENCIL_INDEX, GL_FLOAT, 2, 1).unwrap();
+        assert_eq!(layout.size(), 8);
+        let mut dst = [0; 4];
+        layout.put_index(&mut dst, 200);
+        assert_eq!(dst, 200.0f32.to_ne_bytes());
+        assert_eq!(layout.get_index(&7.0f32.to_ne_bytes()), 7);
+    }
+
+    #[test]
+    fn swap_bytes_reverses_multibyte_elements() {
+        let store = PixelStore {
+            swap_bytes: true,
+            ..store(4)
+        };
+        let layout = Layout::new(&store, GL_STENCIL_INDEX, GL_UNSIGNED_SHORT, 1, 1).unwrap();
+        let mut swapped = 0x1234u16.to_ne_bytes();
+        swapped.reverse();
+        assert_eq!(layout.get_index(&swapped), 0x1234);
+
+        let mut dst = [0; 2];
+        layout.put_index(&mut dst, 0x1234);
+        assert_eq!(dst, swapped);
+
+        let layout = Layout::new(&store, GL_STENCIL_INDEX, GL_UNSIGNED_INT, 1, 1).unwrap();
+        let mut swapped = 0x1234_5678u32.to_ne_bytes();
+        swapped.reverse();
+        assert_eq!(layout.get_index(&swapped), 0x1234_5678);
+    }
+
+    #[test]
+    fn swap_bytes_leaves_bytes_alone() {
+        let store = PixelStore {
+            swap_bytes: true,
+            ..store(4)
+        };
+        let layout = Layout::new(&store, GL_RGBA, GL_UNSIGNED_BYTE, 1, 1).unwrap();
+        assert!(!layout.swap_bytes);
+        assert_eq!(layout.get_color(&[255, 0, 0, 255]), [1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn bgra_components_are_reordered() {
+        let layout = Layout::new(&store(4), GL_BGRA, GL_UNSIGNED_BYTE, 1, 1).unwrap();
+        assert_eq!(layout.get_color(&[0, 0, 255, 255]), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            layout.color_components([1.0, 0.5, 0.0, 1.0]),
+            [0.0, 0.5, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn bitmap_rows_are_padded_to_the_alignment() {
+        let layout = |alignment, width| BitmapLayout::new(&store(alignment), width, 2);
+        assert_eq!(layout(1, 9).stride, 2);
+        assert_eq!(layout(4, 9).stride, 4);
+        assert_eq!(layout(1, 8).stride, 1);
+        assert_eq!(layout(1, 9).size(), 4);
+    }
+
+    #[test]
+    fn bitmap_bit_order() {
+        let msb_first = BitmapLayout::new(&store(1), 8, 1);
+        assert!(msb_first.bit(&[0b1000_0000], 0, 0));
+        assert!(!msb_first.bit(&[0b1000_0000], 7, 0));
+
+        let store = PixelStore {
+            lsb_first: true,
+            ..store(1)
+        };
+        let lsb_first = BitmapLayout::new(&store, 8, 1);
+        assert!(lsb_first.bit(&[0b0000_0001], 0, 0));
+        assert!(lsb_first.bit(&[0b1000_0000], 7, 0));
+    }
+
+    #[test]
+    fn bitmap_skips_are_counted_in_bits() {
+        let store = PixelStore {
+            skip_pixels: 3,
+            skip_rows: 1,
+            ..store(1)
+        };
+        let layout = BitmapLayout::new(&store, 4, 1);
+        assert_eq!(layout.offset, 8 + 3);
+        assert!(layout.bit(&[0, 0b0001_0000], 0, 0));
+        assert_eq!(layout.size(), 2);
+    }
+}
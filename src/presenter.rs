@@ -0,0 +1,14 @@
+//! The interface between contexts and whatever displays their frames.
+//!
+//! The GL state machine and rasterizer know nothing about windows. Each window system front end
+//! implements `Presenter` for its drawables and binds one to a context when it is made current.
+
+/// A drawable that a context's front buffer is shown on.
+pub trait Presenter: Send {
+    /// Returns the drawable's size in pixels, or `None` if there is no way to find out, in which
+    /// case the context keeps its size until told otherwise with mglSetDrawableSize.
+    fn size(&self) -> Option<(usize, usize)>;
+
+    /// Shows a `width` x `height` image of BGRA pixels whose first row is the bottom one.
+    fn present(&mut self, pixels: &[u8], width: usize, height: usize);
+}
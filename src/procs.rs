@@ -0,0 +1,86 @@
+//! The table of entry points reachable through wglGetProcAddress.
+//!
+//! Every extension minigl implements is listed here along with the functions it adds, and both
+//! the GL_EXTENSIONS string and the proc address lookup are generated from this one list, so an
+//! extension cannot be advertised without its functions being reachable (or the reverse).
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    sync::OnceLock,
+};
+
+macro_rules! procs {
+    (
+        core: [$($core:ident),* $(,)?],
+        extensions: {
+            $($(#[cfg($cfg:meta)])? $extension:literal: [$($proc:ident),* $(,)?]),* $(,)?
+        } $(,)?
+    ) => {
+        /// Space separated names of every extension supported on this platform.
+        pub fn extensions() -> &'static CStr {
+            static EXTENSIONS: OnceLock<CString> = OnceLock::new();
+            EXTENSIONS.get_or_init(|| {
+                let names: Vec<&str> = [$(($extension, true $(&& cfg!($cfg))?)),*]
+                    .into_iter()
+                    .filter(|&(_, supported)| supported)
+                    .map(|(name, _)| name)
+                    .collect();
+                CString::new(names.join(" ")).unwrap()
+            })
+        }
+
+        /// Returns the address of the entry point called `name`.
+        pub fn lookup(name: &[u8]) -> Option<*const c_void> {
+            $(
+                if name == stringify!($core).as_bytes() {
+                    return Some(crate::$core as *const c_void);
+                }
+            )*
+            $(
+                $(#[cfg($cfg)])?
+                {
+                    $(
+                        if name == stringify!($proc).as_bytes() {
+                            return Some(crate::$proc as *const c_void);
+                        }
+                    )*
+                }
+            )*
+            None
+        }
+    };
+}
+
+procs! {
+    core: [
+        glAlphaFunc, glAreTexturesResident, glBegin, glBindTexture, glBitmap, glBlendFunc, glClear,
+        glClearColor, glClearDepth, glClearStencil, glColor3f, glColor3ubv, glColor4f, glColor4fv,
+        glColorMask, glCopyPixels, glCullFace, glDeleteTextures, glDepthFunc, glDepthMask,
+        glDepthRange, glDisable, glDrawBuffer, glDrawPixels, glEnable, glEnd, glFinish, glFlush,
+        glFrustum, glGenTextures, glGetFloatv, glGetIntegerv, glGetString, glIsTexture,
+        glLoadIdentity, glMatrixMode, glOrtho, glPixelStoref, glPixelStorei, glPixelZoom,
+        glPolygonMode, glPopMatrix, glPrioritizeTextures, glPushMatrix, glRasterPos2d,
+        glRasterPos2f, glRasterPos2fv, glRasterPos2i, glRasterPos3d, glRasterPos3f, glRasterPos3fv,
+        glRasterPos3i, glRasterPos4f, glReadBuffer, glReadPixels, glRotatef, glScalef, glScissor,
+        glShadeModel, glStencilMask, glTexCoord2f, glTexEnvf, glTexEnvfv, glTexEnvi, glTexEnviv,
+        glTexImage2D, glTexParameterf, glTexSubImage2D, glTranslatef, glVertex2f, glVertex3f,
+        glVertex3fv, glVertex4f, glViewport,
+    ],
+    // WGL extensions are listed too, since games of the era look for them in GL_EXTENSIONS.
+    // Extensions can be limited to some platforms with cfg attributes.
+    extensions: {
+        "GL_ARB_multitexture": [
+            glActiveTextureARB, glClientActiveTextureARB, glMultiTexCoord1fARB,
+            glMultiTexCoord2fARB, glMultiTexCoord2fvARB, glMultiTexCoord3fARB,
+            glMultiTexCoord3fvARB, glMultiTexCoord4fARB, glMultiTexCoord4fvARB,
+        ],
+        "GL_SGIS_multitexture": [glSelectTextureSGIS, glMTexCoord2fSGIS, glMTexCoord2fvSGIS],
+        "GL_ARB_texture_env_add": [],
+        "GL_EXT_texture_env_add": [],
+        "GL_EXT_bgra": [],
+        #[cfg(windows)]
+        "WGL_3DFX_gamma_control": [wglSetDeviceGammaRamp3DFX, wglGetDeviceGammaRamp3DFX],
+        #[cfg(windows)]
+        "WGL_EXT_swap_control": [wglSwapIntervalEXT, wglGetSwapIntervalEXT],
+    },
+}
@@ -1,39 +1,316 @@
-use crate::math::{Vec2, Vec3, Vec4};
+use crate::{
+    format::PixelFormat,
+    math::{Vec2, Vec3, Vec4},
+};
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DepthFunc {
+    Never,
+    Less,
+    Equal,
+    LEqual,
+    Greater,
+    NotEqual,
+    GEqual,
+    Always,
+}
+
+impl DepthFunc {
+    fn passes(self, incoming: f32, stored: f32) -> bool {
+        match self {
+            DepthFunc::Never => false,
+            DepthFunc::Less => incoming < stored,
+            DepthFunc::Equal => incoming == stored,
+            DepthFunc::LEqual => incoming <= stored,
+            DepthFunc::Greater => incoming > stored,
+            DepthFunc::NotEqual => incoming != stored,
+            DepthFunc::GEqual => incoming >= stored,
+            DepthFunc::Always => true,
+        }
+    }
+}
+
+/// One of a framebuffer's color buffers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorBuffer {
+    Front,
+    Back,
+}
+
+/// The color buffers fragments are written to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrawBuffer {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl DrawBuffer {
+    fn includes(self, buffer: ColorBuffer) -> bool {
+        match self {
+            DrawBuffer::None => false,
+            DrawBuffer::Front => buffer == ColorBuffer::Front,
+            DrawBuffer::Back => buffer == ColorBuffer::Back,
+            DrawBuffer::FrontAndBack => true,
+        }
+    }
+}
+
+/// The tests and write masks applied to every fragment, whatever primitive produced it.
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentOps {
+    pub scissor: Option<Rect>,
+    /// `None` if depth testing is disabled, in which case depth is never written either.
+    pub depth_func: Option<DepthFunc>,
+    pub depth_mask: bool,
+    pub color_mask: [bool; 4],
+    pub stencil_mask: u8,
+    pub draw_buffer: DrawBuffer,
+}
 
 pub struct Framebuffer {
     pub width: usize,
     pub height: usize,
-    pub buffer: Vec<u8>,
+    pub format: PixelFormat,
+    /// The front color buffer, stored as BGRA. This is the one that gets presented.
+    pub front: Vec<u8>,
+    /// Empty if the format is single buffered.
+    pub back: Vec<u8>,
+    /// Whether the front buffer has changed since it was last presented.
+    pub front_dirty: bool,
     pub z_buffer: Vec<f32>,
+    /// Empty if the format has no stencil bits.
+    pub stencil_buffer: Vec<u8>,
 }
 
 impl Framebuffer {
-    pub fn new(width: usize, height: usize) -> Self {
+    pub fn new(width: usize, height: usize, format: PixelFormat) -> Self {
+        let size_if = |present: bool| if present { width * height } else { 0 };
+        let stencil_size = size_if(format.stencil_bits > 0);
         Self {
             width,
             height,
-            buffer: vec![0; width * height * 4],
-            z_buffer: vec![0.0; width * height * 4],
+            format,
+            front: vec![0; width * height * 4],
+            back: vec![0; size_if(format.double_buffer) * 4],
+            front_dirty: false,
+            z_buffer: vec![1.0; width * height],
+            stencil_buffer: vec![0; stencil_size],
         }
     }
 
-    pub fn clear(&mut self) {
-        self.buffer.fill(0);
-        self.z_buffer.fill(1.0);
+    /// Rounds a depth value to the precision of the format's depth buffer. Depth is stored as
+    /// f32, which already has about 24 bits of precision in [0, 1], so only 16 bit formats need
+    /// anything done.
+    fn quantize_depth(&self, z: f32) -> f32 {
+        if self.format.depth_bits == 16 {
+            (z * 65535.0).round() / 65535.0
+        } else {
+            z
+        }
     }
 
-    pub fn draw_pixel(&mut self, x: i32, y: i32, color: [u8; 3]) {
-        if 0 <= x && x < self.width as i32 && 0 <= y && y < self.height as i32 {
-            let index = (x as usize + y as usize * self.width) * 4;
-            self.buffer[index] = color[2];
-            self.buffer[index + 1] = color[1];
-            self.buffer[index + 2] = color[0];
+    pub fn color_buffer(&self, buffer: ColorBuffer) -> &[u8] {
+        match buffer {
+            ColorBuffer::Front => &self.front,
+            ColorBuffer::Back => &self.back,
         }
     }
 
-    pub fn draw_triangle<F>(&mut self, verts: [Vec4; 3], shader: F)
+    /// Returns the color buffers selected by `draw_buffer` that exist.
+    fn draw_targets(&mut self, draw_buffer: DrawBuffer) -> impl Iterator<Item = &mut Vec<u8>> {
+        self.front_dirty |= draw_buffer.includes(ColorBuffer::Front);
+        [
+            (ColorBuffer::Front, &mut self.front),
+            (ColorBuffer::Back, &mut self.back),
+        ]
+        .into_iter()
+        .filter(move |(buffer, data)| draw_buffer.includes(*buffer) && !data.is_empty())
+        .map(|(_, data)| data)
+    }
+
+    /// Presents the back buffer by exchanging it with the front buffer, or copying it over the
+    /// front buffer if the format asks for that. Does nothing for single buffered formats.
+    pub fn swap(&mut self) {
+        if self.back.is_empty() {
+            return;
+        }
+        if self.format.swap_copy {
+            self.front.copy_from_slice(&self.back);
+        } else {
+            std::mem::swap(&mut self.front, &mut self.back);
+        }
+        self.front_dirty = true;
+    }
+
+    /// Returns the stencil value at `index`, or 0 if there is no stencil buffer.
+    pub fn stencil_at(&self, index: usize) -> u8 {
+        self.stencil_buffer.get(index).copied().unwrap_or(0)
+    }
+
+    /// Returns the pixel ranges covered by `rect` after clipping it to the framebuffer.
+    fn clip(&self, rect: Rect) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let x0 = rect.x.clamp(0, self.width as i32) as usize;
+        let y0 = rect.y.clamp(0, self.height as i32) as usize;
+        let x1 = (rect.x.saturating_add(rect.width)).clamp(0, self.width as i32) as usize;
+        let y1 = (rect.y.saturating_add(rect.height)).clamp(0, self.height as i32) as usize;
+        (x0..x1.max(x0), y0..y1.max(y0))
+    }
+
+    /// Fills `rect` in the buffers selected by `draw_buffer` with an RGBA `color`, leaving
+    /// channels whose `mask` entry is false untouched.
+    pub fn clear_color(
+        &mut self,
+        draw_buffer: DrawBuffer,
+        rect: Rect,
+        color: [u8; 4],
+        mask: [bool; 4],
+    ) {
+        let (xs, ys) = self.clip(rect);
+        let width = self.width;
+        // stored as BGRA
+        let bgra = [color[2], color[1], color[0], color[3]];
+        let mask = [mask[2], mask[1], mask[0], mask[3]];
+        for buffer in self.draw_targets(draw_buffer) {
+            for y in ys.clone() {
+                let row = &mut buffer[(xs.start + y * width) * 4..(xs.end + y * width) * 4];
+                if mask == [true; 4] {
+                    for pixel in row.chunks_exact_mut(4) {
+                        pixel.copy_from_slice(&bgra);
+                    }
+                } else {
+                    for pixel in row.chunks_exact_mut(4) {
+                        for i in 0..4 {
+                            if mask[i] {
+                                pixel[i] = bgra[i];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn clear_depth(&mut self, rect: Rect, depth: f32) {
+        let depth = self.quantize_depth(depth);
+        let (xs, ys) = self.clip(rect);
+        for y in ys {
+            self.z_buffer[xs.start + y * self.width..xs.end + y * self.width].fill(depth);
+        }
+    }
+
+    /// Sets the bits selected by `mask` in every stencil value inside `rect` to those of `value`.
+    pub fn clear_stencil(&mut self, rect: Rect, value: u8, mask: u8) {
+        if self.stencil_buffer.is_empty() {
+            return;
+        }
+        let (xs, ys) = self.clip(rect);
+        for y in ys {
+            for stencil in
+                &mut self.stencil_buffer[xs.start + y * self.width..xs.end + y * self.width]
+            {
+                *stencil = (*stencil & !mask) | (value & mask);
+            }
+        }
+    }
+
+    /// Returns the RGBA color stored at (`x`, `y`) in `buffer`.
+    pub fn color_at(&self, buffer: ColorBuffer, x: usize, y: usize) -> [u8; 4] {
+        let index = (x + y * self.width) * 4;
+        let bgra = &self.color_buffer(buffer)[index..index + 4];
+        // formats without alpha read back as fully opaque
+        let alpha = if self.format.alpha_bits > 0 {
+            bgra[3]
+        } else {
+            255
+        };
+        [bgra[2], bgra[1], bgra[0], alpha]
+    }
+
+    /// Runs the ownership, scissor and depth tests for a fragment, updating the depth buffer if
+    /// they pass. Returns the index of the pixel the fragment's color should be written to.
+    pub fn test_fragment(&mut self, ops: &FragmentOps, x: i32, y: i32, z: f32) -> Option<usize> {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return None;
+        }
+        if let Some(scissor) = ops.scissor {
+            if x < scissor.x
+                || y < scissor.y
+                || x - scissor.x >= scissor.width
+                || y - scissor.y >= scissor.height
+            {
+                return None;
+            }
+        }
+
+        let index = x as usize + y as usize * self.width;
+        if let Some(func) = ops.depth_func {
+            let z = self.quantize_depth(z);
+            if !func.passes(z, self.z_buffer[index]) {
+                return None;
+            }
+            if ops.depth_mask {
+                self.z_buffer[index] = z;
+            }
+        }
+
+        Some(index)
+    }
+
+    /// Writes an RGBA color to the pixel at `index`, as returned by `test_fragment`, in each of
+    /// the draw buffers.
+    pub fn write_color(&mut self, ops: &FragmentOps, index: usize, color: [u8; 4]) {
+        let bgra = [color[2], color[1], color[0], color[3]];
+        let mask = [
+            ops.color_mask[2],
+            ops.color_mask[1],
+            ops.color_mask[0],
+            ops.color_mask[3],
+        ];
+        for buffer in self.draw_targets(ops.draw_buffer) {
+            let pixel = &mut buffer[index * 4..index * 4 + 4];
+            for i in 0..4 {
+                if mask[i] {
+                    pixel[i] = bgra[i];
+                }
+            }
+        }
+    }
+
+    /// Tests a fragment and writes its color if it passes.
+    pub fn draw_fragment(&mut self, ops: &FragmentOps, x: i32, y: i32, z: f32, color: [u8; 4]) {
+        if let Some(index) = self.test_fragment(ops, x, y, z) {
+            self.write_color(ops, index, color);
+        }
+    }
+
+    /// Writes a stencil value directly, as glDrawPixels does for stencil indices.
+    pub fn draw_stencil(&mut self, ops: &FragmentOps, x: i32, y: i32, value: u8) {
+        let ops = FragmentOps {
+            depth_func: None,
+            ..*ops
+        };
+        if self.stencil_buffer.is_empty() {
+            return;
+        }
+        if let Some(index) = self.test_fragment(&ops, x, y, 0.0) {
+            let stencil = &mut self.stencil_buffer[index];
+            *stencil = (*stencil & !ops.stencil_mask) | (value & ops.stencil_mask);
+        }
+    }
+
+    pub fn draw_triangle<F>(&mut self, ops: &FragmentOps, verts: [Vec4; 3], shader: F)
     where
-        F: Fn(Vec3) -> Vec3,
+        F: Fn(Vec3) -> Vec4,
     {
         let (mut min_x, mut min_y) = (self.width as i32, self.height as i32);
         let (mut max_x, mut max_y) = (0, 0);
@@ -48,32 +325,31 @@ impl Framebuffer {
         max_x = (max_x + 1).clamp(0, self.width as i32);
         max_y = (max_y + 1).clamp(0, self.height as i32);
 
-        let (ooza, oozb, oozc) = (1.0 / verts[0].z, 1.0 / verts[1].z, 1.0 / verts[2].z);
         let (oowa, oowb, oowc) = (1.0 / verts[0].w, 1.0 / verts[1].w, 1.0 / verts[2].w);
         let (a, b, c) = (verts[0].xy(), verts[1].xy(), verts[2].xy());
         let invarea = 1.0 / (b - a).perp().dot(c - a);
 
         for y in min_y..max_y {
             for x in min_x..max_x {
-                let p = Vec2::new(x as f32, y as f32);
+                // sample at pixel centers
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
                 let bary = Vec3::new(
                     (b - p).perp().dot(c - p) * invarea,
                     (c - p).perp().dot(a - p) * invarea,
                     (a - p).perp().dot(b - p) * invarea,
                 );
                 if bary[0] >= 0.0 && bary[1] >= 0.0 && bary[2] >= 0.0 {
-                    let z = 1.0 / (bary[0] * ooza + bary[1] * oozb + bary[2] * oozc);
+                    // window space depth is affine in screen space, so no perspective correction
+                    let z = bary[0] * verts[0].z + bary[1] * verts[1].z + bary[2] * verts[2].z;
                     let w = 1.0 / (bary[0] * oowa + bary[1] * oowb + bary[2] * oowc);
-                    if z < self.z_buffer[x as usize + y as usize * self.width] {
-                        self.z_buffer[x as usize + y as usize * self.width] = z;
-
+                    if let Some(index) = self.test_fragment(ops, x, y, z) {
                         // correct for perspective
                         // TODO understand this better
                         // TODO why does doing this break menus?
                         let bary = Vec3::new(bary[0] * oowa, bary[1] * oowb, bary[2] * oowc) * w;
 
-                        let color = shader(bary) * 255.0;
-                        self.draw_pixel(x, y, [color[0] as u8, color[1] as u8, color[2] as u8]);
+                        let color = shader(bary).as_array().map(|c| (c * 255.0) as u8);
+                        self.write_color(ops, index, color);
                     }
                 }
             }
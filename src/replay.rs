@@ -0,0 +1,558 @@
+//! Replaying call traces against the GL core, with no window system, so that rendering problems
+//! recorded on one machine can be reproduced on another.
+//!
+//! Window system calls are replaced by offscreen contexts sized like the traced windows were.
+//! Queries are skipped, since their results would go nowhere, and so are frame callbacks, which
+//! cannot be replayed. GL calls made with no context current, as in traces of applications that
+//! use some other window system API, get a context of the default pixel format created for them.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    context::{self, Context, Handle},
+    format,
+    presenter::Presenter,
+    rasterize::Framebuffer,
+    trace::{Args, Call, Record},
+    unpack_bitmap_size, unpack_size, GLState, GLenum, GL_MODELVIEW, GL_PROJECTION, GL_TEXTURE,
+    GL_TEXTURE_2D, GL_TEXTURE_ENV_COLOR,
+};
+
+/// Stands in for a traced window, reporting whatever size it had at the time.
+struct ReplayPresenter {
+    size: Arc<Mutex<(usize, usize)>>,
+}
+
+impl Presenter for ReplayPresenter {
+    fn size(&self) -> Option<(usize, usize)> {
+        Some(*self.size.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn present(&mut self, _pixels: &[u8], _width: usize, _height: usize) {}
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Returns a pointer to pointed-to data read from a trace, checking that there is at least
+/// `needed` of it, since the call will read that much.
+fn data_ptr<T>(data: &Option<Vec<T>>, needed: usize) -> io::Result<*const T> {
+    match data {
+        Some(data) if data.len() < needed => Err(invalid("call data is too short")),
+        Some(data) => Ok(data.as_ptr()),
+        None => Ok(std::ptr::null()),
+    }
+}
+
+/// The number of values `glTexEnvfv` and `glTexEnviv` read for `pname`.
+fn tex_env_count(pname: GLenum) -> usize {
+    if pname == GL_TEXTURE_ENV_COLOR {
+        4
+    } else {
+        1
+    }
+}
+
+pub struct Replayer {
+    /// The context replaying each traced one, by the handle it had when traced.
+    contexts: BTreeMap<u32, Handle>,
+    /// The drawable size each replaying context's presenter reports.
+    sizes: BTreeMap<Handle, Arc<Mutex<(usize, usize)>>>,
+    /// The context each traced thread has current.
+    threads: BTreeMap<u32, Handle>,
+    /// The size of contexts created for GL calls made with no context current.
+    default_size: (usize, usize),
+}
+
+impl Replayer {
+    pub fn new(default_size: (usize, usize)) -> Self {
+        Self {
+            contexts: BTreeMap::new(),
+            sizes: BTreeMap::new(),
+            threads: BTreeMap::new(),
+            default_size,
+        }
+    }
+
+    /// Runs `f` on the framebuffer of the context that is current after the last call replayed.
+    pub fn framebuffer<R>(&self, f: impl FnOnce(&Framebuffer) -> R) -> Option<R> {
+        let handle = context::current()?;
+        context::with_context(handle, |context| f(&context.state.fb))
+    }
+
+    fn create_context(&mut self, format: format::PixelFormat, size: (usize, usize)) -> Handle {
+        let mut context = Context::new(GLState::new(size.0, size.1, format));
+        // replays run as fast as they can
+        context.pacer.interval = 0;
+        let handle = context::create(context);
+        self.sizes.insert(handle, Arc::new(Mutex::new(size)));
+        handle
+    }
+
+    /// Binds a replaying context to a drawable of `size`, like making it current on a window.
+    fn bind(&mut self, thread: u32, handle: Handle, size: (usize, usize)) {
+        let shared = self.sizes[&handle].clone();
+        *shared.lock().unwrap_or_else(|e| e.into_inner()) = size;
+        context::make_current(handle);
+        context::with_current(|context| {
+            context.set_presenter(Box::new(ReplayPresenter { size: shared }))
+        });
+        self.threads.insert(thread, handle);
+    }
+
+    /// Replays one call, returning true if it ended a frame by swapping buffers. Calls this
+    /// version of minigl does not know about are skipped, and calls it cannot carry out, which
+    /// would crash the replay, are errors.
+    pub fn replay(&mut self, record: &Record) -> io::Result<bool> {
+        let Some(call) = record.call() else {
+            return Ok(false);
+        };
+
+        // the traced threads take turns on this one
+        context::make_current(self.threads.get(&record.thread).copied().unwrap_or(0));
+        if call.name().starts_with("gl") && context::current().is_none() {
+            let handle = self.create_context(Default::default(), self.default_size);
+            self.bind(record.thread, handle, self.default_size);
+        }
+
+        let mut args = record.args();
+        if call.name().starts_with("wgl") {
+            return self.replay_wgl(call, record.thread, &mut args);
+        }
+        replay_gl(call, &mut args)?;
+        Ok(false)
+    }
+
+    fn replay_wgl(&mut self, call: Call, thread: u32, args: &mut Args) -> io::Result<bool> {
+        let size = |args: &mut Args| -> io::Result<(usize, usize)> {
+            let [width, height]: [u32; 2] = args.get()?;
+            Ok((width as usize, height as usize))
+        };
+
+        match call {
+            Call::wglCreateContext => {
+                let _hdc: u32 = args.get()?;
+                let traced: u32 = args.get()?;
+                let index: i32 = args.get()?;
+                let size = size(args)?;
+                let format = usize::try_from(index - 1)
+                    .ok()
+                    .and_then(|index| format::supported().nth(index))
+                    .unwrap_or_default();
+                let handle = self.create_context(format, size);
+                self.contexts.insert(traced, handle);
+            }
+            Call::wglMakeCurrent => {
+                let _hdc: u32 = args.get()?;
+                let traced: u32 = args.get()?;
+                let size = size(args)?;
+                match self.contexts.get(&traced) {
+                    Some(&handle) => self.bind(thread, handle, size),
+                    None => {
+                        // releasing, or a context that failed to be created
+                        context::make_current(0);
+                        self.threads.remove(&thread);
+                    }
+                }
+            }
+            Call::wglDeleteContext => {
+                let traced: u32 = args.get()?;
+                if let Some(handle) = self.contexts.remove(&traced) {
+                    context::delete(handle);
+                    self.sizes.remove(&handle);
+                    self.threads.retain(|_, &mut current| current != handle);
+                }
+            }
+            Call::wglShareLists => {
+                let (first, second): (u32, u32) = (args.get()?, args.get()?);
+                let (Some(&first), Some(&second)) =
+                    (self.contexts.get(&first), self.contexts.get(&second))
+                else {
+                    return Ok(false);
+                };
+                let namespace =
+                    context::with_context(first, |context| context.state.namespace.clone());
+                if let Some(namespace) = namespace {
+                    context::with_context(second, |context| {
+                        // the traced call failed if there were objects it would have thrown away
+                        let state = &mut context.state;
+                        if state.namespace.lock().textures.is_empty() {
+                            state.namespace = namespace;
+                        }
+                    });
+                }
+            }
+            Call::wglSwapBuffers => {
+                let _hdc: u32 = args.get()?;
+                let size = size(args)?;
+                let Some(handle) = context::current() else {
+                    return Ok(false);
+                };
+                *self.sizes[&handle]
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner()) = size;
+                context::swap_current();
+                return Ok(true);
+            }
+            Call::wglSetDeviceGammaRamp3DFX => {
+                let _hdc: u32 = args.get()?;
+                let ramp: Option<Vec<u16>> = args.get()?;
+                crate::mglSetGammaRamp(data_ptr(&ramp, 3 * 256)?);
+            }
+            // pixel formats are only needed for creating contexts, which records the one it got,
+            // and swap intervals would only slow the replay down
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+fn replay_gl(call: Call, args: &mut Args) -> io::Result<()> {
+    match call {
+        Call::mglSetDrawableSize => crate::mglSetDrawableSize(args.get()?, args.get()?),
+        Call::glFlush => crate::glFlush(),
+        Call::glFinish => crate::glFinish(),
+        Call::glClearColor => {
+            crate::glClearColor(args.get()?, args.get()?, args.get()?, args.get()?)
+        }
+        Call::glClearDepth => crate::glClearDepth(args.get()?),
+        Call::glClearStencil => crate::glClearStencil(args.get()?),
+        Call::glClear => crate::glClear(args.get()?),
+        Call::glScissor => crate::glScissor(args.get()?, args.get()?, args.get()?, args.get()?),
+        Call::glColorMask => crate::glColorMask(args.get()?, args.get()?, args.get()?, args.get()?),
+        Call::glStencilMask => crate::glStencilMask(args.get()?),
+        Call::glCullFace => crate::glCullFace(args.get()?),
+        Call::glEnable => crate::glEnable(args.get()?),
+        Call::glDisable => crate::glDisable(args.get()?),
+        Call::glAlphaFunc => crate::glAlphaFunc(args.get()?, args.get()?),
+        Call::glBlendFunc => crate::glBlendFunc(args.get()?, args.get()?),
+        Call::glDepthFunc => crate::glDepthFunc(args.get()?),
+        Call::glDepthRange => crate::glDepthRange(args.get()?, args.get()?),
+        Call::glDepthMask => crate::glDepthMask(args.get()?),
+        Call::glPolygonMode => crate::glPolygonMode(args.get()?, args.get()?),
+        Call::glShadeModel => crate::glShadeModel(args.get()?),
+        Call::glTexParameterf => crate::glTexParameterf(args.get()?, args.get()?, args.get()?),
+        Call::glTexEnvi => crate::glTexEnvi(args.get()?, args.get()?, args.get()?),
+        Call::glTexEnvf => crate::glTexEnvf(args.get()?, args.get()?, args.get()?),
+        Call::glActiveTextureARB => crate::glActiveTextureARB(args.get()?),
+        Call::glClientActiveTextureARB => crate::glClientActiveTextureARB(args.get()?),
+        Call::glMultiTexCoord4fARB => crate::glMultiTexCoord4fARB(
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+        ),
+        Call::glMultiTexCoord1fARB => crate::glMultiTexCoord1fARB(args.get()?, args.get()?),
+        Call::glMultiTexCoord2fARB => {
+            crate::glMultiTexCoord2fARB(args.get()?, args.get()?, args.get()?)
+        }
+        Call::glMultiTexCoord3fARB => {
+            crate::glMultiTexCoord3fARB(args.get()?, args.get()?, args.get()?, args.get()?)
+        }
+        Call::glMultiTexCoord2fvARB => crate::glMultiTexCoord2fvARB(args.get()?, &args.get()?),
+        Call::glMultiTexCoord3fvARB => crate::glMultiTexCoord3fvARB(args.get()?, &args.get()?),
+        Call::glMultiTexCoord4fvARB => crate::glMultiTexCoord4fvARB(args.get()?, &args.get()?),
+        Call::glSelectTextureSGIS => crate::glSelectTextureSGIS(args.get()?),
+        Call::glMTexCoord2fSGIS => crate::glMTexCoord2fSGIS(args.get()?, args.get()?, args.get()?),
+        Call::glMTexCoord2fvSGIS => crate::glMTexCoord2fvSGIS(args.get()?, &args.get()?),
+        Call::glBindTexture => crate::glBindTexture(args.get()?, args.get()?),
+        Call::glViewport => crate::glViewport(args.get()?, args.get()?, args.get()?, args.get()?),
+        Call::glMatrixMode => {
+            let mode = args.get()?;
+            if ![GL_MODELVIEW, GL_PROJECTION, GL_TEXTURE].contains(&mode) {
+                return Err(invalid(&format!("unsupported matrix mode {mode:#x}")));
+            }
+            crate::glMatrixMode(mode);
+        }
+        Call::glLoadIdentity => crate::glLoadIdentity(),
+        Call::glOrtho => crate::glOrtho(
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+        ),
+        Call::glFrustum => crate::glFrustum(
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+        ),
+        Call::glColor3f => crate::glColor3f(args.get()?, args.get()?, args.get()?),
+        Call::glColor3ubv => crate::glColor3ubv(&args.get()?),
+        Call::glColor4f => crate::glColor4f(args.get()?, args.get()?, args.get()?, args.get()?),
+        Call::glColor4fv => crate::glColor4fv(&args.get()?),
+        Call::glBegin => crate::glBegin(args.get()?),
+        Call::glEnd => crate::glEnd(),
+        Call::glTexCoord2f => crate::glTexCoord2f(args.get()?, args.get()?),
+        Call::glVertex2f => crate::glVertex2f(args.get()?, args.get()?),
+        Call::glVertex3f => crate::glVertex3f(args.get()?, args.get()?, args.get()?),
+        Call::glVertex3fv => crate::glVertex3fv(&args.get()?),
+        Call::glVertex4f => crate::glVertex4f(args.get()?, args.get()?, args.get()?, args.get()?),
+        Call::glDrawBuffer => crate::glDrawBuffer(args.get()?),
+        Call::glReadBuffer => crate::glReadBuffer(args.get()?),
+        Call::glRotatef => crate::glRotatef(args.get()?, args.get()?, args.get()?, args.get()?),
+        Call::glTranslatef => crate::glTranslatef(args.get()?, args.get()?, args.get()?),
+        Call::glScalef => crate::glScalef(args.get()?, args.get()?, args.get()?),
+        Call::glPushMatrix => crate::glPushMatrix(),
+        Call::glPopMatrix => crate::glPopMatrix(),
+        Call::glPixelStorei => crate::glPixelStorei(args.get()?, args.get()?),
+        Call::glPixelStoref => crate::glPixelStoref(args.get()?, args.get()?),
+        Call::glRasterPos2i => crate::glRasterPos2i(args.get()?, args.get()?),
+        Call::glRasterPos2f => crate::glRasterPos2f(args.get()?, args.get()?),
+        Call::glRasterPos2d => crate::glRasterPos2d(args.get()?, args.get()?),
+        Call::glRasterPos3i => crate::glRasterPos3i(args.get()?, args.get()?, args.get()?),
+        Call::glRasterPos3f => crate::glRasterPos3f(args.get()?, args.get()?, args.get()?),
+        Call::glRasterPos3d => crate::glRasterPos3d(args.get()?, args.get()?, args.get()?),
+        Call::glRasterPos2fv => crate::glRasterPos2fv(&args.get()?),
+        Call::glRasterPos3fv => crate::glRasterPos3fv(&args.get()?),
+        Call::glRasterPos4f => {
+            crate::glRasterPos4f(args.get()?, args.get()?, args.get()?, args.get()?)
+        }
+        Call::glPixelZoom => crate::glPixelZoom(args.get()?, args.get()?),
+        Call::glCopyPixels => crate::glCopyPixels(
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+            args.get()?,
+        ),
+        Call::mglSetGammaRamp => {
+            let ramp: Option<Vec<u16>> = args.get()?;
+            crate::mglSetGammaRamp(data_ptr(&ramp, 3 * 256)?);
+        }
+        Call::glTexEnvfv => {
+            let (target, pname) = (args.get()?, args.get()?);
+            let params: Option<Vec<f32>> = args.get()?;
+            let params = data_ptr(&params, tex_env_count(pname))?;
+            crate::glTexEnvfv(target, pname, params);
+        }
+        Call::glTexEnviv => {
+            let (target, pname) = (args.get()?, args.get()?);
+            let params: Option<Vec<i32>> = args.get()?;
+            let params = data_ptr(&params, tex_env_count(pname))?;
+            crate::glTexEnviv(target, pname, params);
+        }
+        Call::glGenTextures => {
+            let n: i32 = args.get()?;
+            let traced: Option<Vec<u32>> = args.get()?;
+            let mut textures = vec![0; n.max(0) as usize];
+            let ptr = match traced {
+                Some(_) => textures.as_mut_ptr(),
+                None => std::ptr::null_mut(),
+            };
+            crate::glGenTextures(n, ptr);
+            // later calls use the names the traced application got, so they have to be the same
+            if traced.is_some_and(|traced| traced != textures) {
+                return Err(invalid(
+                    "glGenTextures returned different names than were traced",
+                ));
+            }
+        }
+        Call::glDeleteTextures => {
+            let n: i32 = args.get()?;
+            let textures: Option<Vec<u32>> = args.get()?;
+            crate::glDeleteTextures(n, data_ptr(&textures, n.max(0) as usize)?);
+        }
+        Call::glAreTexturesResident => {
+            let n: i32 = args.get()?;
+            let textures: Option<Vec<u32>> = args.get()?;
+            let mut residences = vec![0; n.max(0) as usize];
+            let textures = data_ptr(&textures, n.max(0) as usize)?;
+            crate::glAreTexturesResident(n, textures, residences.as_mut_ptr());
+        }
+        Call::glPrioritizeTextures => {
+            let n: i32 = args.get()?;
+            let textures: Option<Vec<u32>> = args.get()?;
+            let priorities: Option<Vec<f32>> = args.get()?;
+            let len = n.max(0) as usize;
+            crate::glPrioritizeTextures(n, data_ptr(&textures, len)?, data_ptr(&priorities, len)?);
+        }
+        Call::glTexImage2D => {
+            let (target, level, internal_format, width, height, border, format, type_) = (
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+            );
+            if target != GL_TEXTURE_2D {
+                return Err(invalid(&format!("unsupported texture target {target:#x}")));
+            }
+            let data: Option<Vec<u8>> = args.get()?;
+            let data = data_ptr(&data, unpack_size(format, type_, width, height))?;
+            crate::glTexImage2D(
+                target,
+                level,
+                internal_format,
+                width,
+                height,
+                border,
+                format,
+                type_,
+                data.cast(),
+            );
+        }
+        Call::glDrawPixels => {
+            let (width, height, format, type_) =
+                (args.get()?, args.get()?, args.get()?, args.get()?);
+            let pixels: Option<Vec<u8>> = args.get()?;
+            let pixels = data_ptr(&pixels, unpack_size(format, type_, width, height))?;
+            crate::glDrawPixels(width, height, format, type_, pixels.cast());
+        }
+        Call::glBitmap => {
+            let (width, height) = (args.get()?, args.get()?);
+            let (xorig, yorig, xmove, ymove) = (args.get()?, args.get()?, args.get()?, args.get()?);
+            let bitmap: Option<Vec<u8>> = args.get()?;
+            let bitmap = data_ptr(&bitmap, unpack_bitmap_size(width, height))?;
+            crate::glBitmap(width, height, xorig, yorig, xmove, ymove, bitmap);
+        }
+        Call::glTexSubImage2D => {
+            let (target, level, xoffset, yoffset, width, height, format, type_) = (
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+                args.get()?,
+            );
+            let pixels: Option<Vec<u8>> = args.get()?;
+            let pixels = data_ptr(&pixels, unpack_size(format, type_, width, height))?;
+            crate::glTexSubImage2D(
+                target,
+                level,
+                xoffset,
+                yoffset,
+                width,
+                height,
+                format,
+                type_,
+                pixels.cast(),
+            );
+        }
+        // queries, readbacks and frame callbacks
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        embed, rasterize::ColorBuffer, trace::Arg, PrimitiveMode, GL_COLOR_BUFFER_BIT, GL_RGBA,
+        GL_TEXTURE_ENV, GL_UNSIGNED_BYTE,
+    };
+
+    fn record(call: Call, args: &[&dyn Arg]) -> Record {
+        let mut data = Vec::new();
+        for arg in args {
+            arg.encode(&mut data);
+        }
+        Record {
+            id: call as u16,
+            thread: 0,
+            args: data,
+        }
+    }
+
+    fn replay(replayer: &mut Replayer, records: &[Record]) -> io::Result<bool> {
+        let mut swapped = false;
+        for record in records {
+            swapped = replayer.replay(record)?;
+        }
+        Ok(swapped)
+    }
+
+    #[test]
+    fn replays_draw_like_the_traced_calls() {
+        let mut replayer = Replayer::new((8, 8));
+        let swapped = replay(
+            &mut replayer,
+            &[
+                record(Call::glClearColor, &[&0.0f32, &0.0f32, &1.0f32, &1.0f32]),
+                record(Call::glClear, &[&GL_COLOR_BUFFER_BIT]),
+                record(Call::glBegin, &[&PrimitiveMode::Triangles]),
+                record(Call::glColor3f, &[&1.0f32, &0.5f32, &0.0f32]),
+                record(Call::glVertex2f, &[&-0.9f32, &-0.9f32]),
+                record(Call::glVertex2f, &[&0.9f32, &-0.9f32]),
+                record(Call::glVertex2f, &[&0.0f32, &0.9f32]),
+                record(Call::glEnd, &[]),
+                record(Call::wglSwapBuffers, &[&0u32, &[8u32, 8]]),
+            ],
+        )
+        .unwrap();
+        assert!(swapped);
+
+        let gl = embed::Context::new(8, 8);
+        gl.clear_color(0.0, 0.0, 1.0, 1.0);
+        gl.clear(true, false, false);
+        gl.begin(PrimitiveMode::Triangles);
+        gl.color3(1.0, 0.5, 0.0);
+        gl.vertex2(-0.9, -0.9);
+        gl.vertex2(0.9, -0.9);
+        gl.vertex2(0.0, 0.9);
+        gl.end();
+        gl.swap_buffers();
+
+        let expected = gl.framebuffer(|fb| fb.color_buffer(ColorBuffer::Front).to_vec());
+        // BGRA, and the triangle covers the middle
+        assert_eq!(expected[(4 * 8 + 4) * 4..][..4], [0, 127, 255, 255]);
+        let replayed = replayer
+            .framebuffer(|fb| fb.color_buffer(ColorBuffer::Front).to_vec())
+            .unwrap();
+        assert_eq!(replayed, expected);
+    }
+
+    #[test]
+    fn calls_minigl_would_panic_on_are_errors() {
+        let invalid: &[&[Record]] = &[
+            &[record(Call::glMatrixMode, &[&0x1234u32])],
+            &[record(
+                Call::glTexImage2D,
+                &[
+                    &0x0de0u32,
+                    &0i32,
+                    &(GL_RGBA as i32),
+                    &1i32,
+                    &1i32,
+                    &0i32,
+                    &GL_RGBA,
+                    &GL_UNSIGNED_BYTE,
+                    &Some(&[0u8; 4][..]),
+                ],
+            )],
+            &[record(
+                Call::glTexEnvfv,
+                &[&GL_TEXTURE_ENV, &GL_TEXTURE_ENV_COLOR, &Some(&[1.0f32][..])],
+            )],
+        ];
+        for (i, records) in invalid.iter().enumerate() {
+            let mut replayer = Replayer::new((4, 4));
+            let err = replay(&mut replayer, records).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "case {i}: {err}");
+        }
+    }
+
+    #[test]
+    fn texture_names_are_checked() {
+        let mut replayer = Replayer::new((4, 4));
+        let gen = |names: &[u32]| record(Call::glGenTextures, &[&2i32, &Some(names)]);
+        replay(&mut replayer, &[gen(&[1, 2])]).unwrap();
+        assert!(replay(&mut replayer, &[gen(&[7, 8])]).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Frame sinks, which are handed every frame a context swaps.
+//!
+//! Sinks see the color buffer as rendered, before the gamma ramp is applied for presentation, so
+//! recorders and test harnesses get the same pixels whatever the brightness setting.
+
+use std::ffi::c_void;
+
+use crate::{GLenum, GLsizei, GL_BGRA};
+
+/// Identifies a sink added to a context, so it can be removed again. 0 is never a valid id.
+pub type SinkId = u32;
+
+/// How the pixels of a `Frame` are stored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameFormat {
+    /// Four bytes per pixel: blue, green, red, alpha.
+    Bgra8,
+}
+
+/// A swapped frame. Rows are listed bottom row first, like GL's window coordinates.
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// Distance between the starts of consecutive rows of `pixels`, in bytes.
+    pub stride: usize,
+    pub format: FrameFormat,
+    pub pixels: &'a [u8],
+    /// The depth buffer, `width` values in [0, 1] per row.
+    pub depth: &'a [f32],
+}
+
+pub trait FrameSink: Send {
+    fn frame(&mut self, frame: &Frame);
+}
+
+impl<F: FnMut(&Frame) + Send> FrameSink for F {
+    fn frame(&mut self, frame: &Frame) {
+        self(frame)
+    }
+}
+
+/// The callback type taken by mglAddFrameCallback.
+pub type FrameCallback = extern "system" fn(
+    user_data: *mut c_void,
+    pixels: *const c_void,
+    width: GLsizei,
+    height: GLsizei,
+    stride: GLsizei,
+    format: GLenum,
+);
+
+/// A sink added through the C API.
+pub struct CallbackSink {
+    pub callback: FrameCallback,
+    /// The caller's pointer, which is only ever handed back to it.
+    pub user_data: usize,
+}
+
+impl FrameSink for CallbackSink {
+    fn frame(&mut self, frame: &Frame) {
+        let format = match frame.format {
+            FrameFormat::Bgra8 => GL_BGRA,
+        };
+        (self.callback)(
+            self.user_data as *mut c_void,
+            frame.pixels.as_ptr().cast(),
+            frame.width as GLsizei,
+            frame.height as GLsizei,
+            frame.stride as GLsizei,
+            format,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+    use crate::{
+        context, embed, format::PixelFormat, GLint, GL_DOUBLEBUFFER, GL_RGBA, GL_UNSIGNED_BYTE,
+    };
+
+    #[test]
+    fn sinks_can_make_gl_calls() {
+        let format = PixelFormat {
+            double_buffer: false,
+            ..Default::default()
+        };
+        let gl = embed::Context::with_format(4, 4, format);
+        let pixel = Arc::new(Mutex::new(None));
+        let seen = pixel.clone();
+        gl.add_frame_sink(move |_: &Frame| {
+            let mut rgba = [0u8; 4];
+            let ptr = rgba.as_mut_ptr().cast();
+            crate::glReadPixels(1, 1, 1, 1, GL_RGBA, GL_UNSIGNED_BYTE, ptr);
+            *seen.lock().unwrap() = Some(rgba);
+        });
+        gl.clear_color(1.0, 0.0, 0.0, 1.0);
+        gl.clear(true, false, false);
+        gl.swap_buffers();
+        assert_eq!(*pixel.lock().unwrap(), Some([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn sinks_can_remove_themselves() {
+        let gl = embed::Context::new(4, 4);
+        let id = Arc::new(AtomicU32::new(0));
+        let frames = Arc::new(AtomicU32::new(0));
+        let (own_id, count) = (id.clone(), frames.clone());
+        id.store(
+            gl.add_frame_sink(move |_: &Frame| {
+                count.fetch_add(1, Ordering::Relaxed);
+                crate::mglRemoveFrameCallback(own_id.load(Ordering::Relaxed));
+            }),
+            Ordering::Relaxed,
+        );
+        gl.swap_buffers();
+        gl.swap_buffers();
+        assert_eq!(frames.load(Ordering::Relaxed), 1);
+        assert!(!gl.remove_frame_sink(id.load(Ordering::Relaxed)));
+    }
+
+    #[test]
+    fn callbacks_can_make_gl_calls() {
+        static DOUBLE_BUFFERED: AtomicU32 = AtomicU32::new(0);
+        extern "system" fn callback(
+            _user_data: *mut c_void,
+            _pixels: *const c_void,
+            _width: GLsizei,
+            _height: GLsizei,
+            _stride: GLsizei,
+            _format: GLenum,
+        ) {
+            let mut double_buffered: GLint = 0;
+            crate::glGetIntegerv(GL_DOUBLEBUFFER, &mut double_buffered);
+            DOUBLE_BUFFERED.store(double_buffered as u32, Ordering::Relaxed);
+        }
+
+        let state = crate::GLState::new(12, 4, Default::default());
+        let handle = context::create(context::Context::new(state));
+        assert!(context::make_current(handle));
+        assert_eq!(crate::mglAddFrameCallback(None, std::ptr::null_mut()), 0);
+        let id = crate::mglAddFrameCallback(Some(callback), std::ptr::null_mut());
+        context::swap_current();
+        assert_eq!(DOUBLE_BUFFERED.load(Ordering::Relaxed), 1);
+
+        crate::mglRemoveFrameCallback(id);
+        context::make_current(0);
+        context::delete(handle);
+    }
+}
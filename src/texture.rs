@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use crate::math::{Mat4, Vec3, Vec4};
+
+pub const MAX_TEXTURE_UNITS: usize = 4;
+
+/// The base internal format of a texture, which decides how it combines with fragment colors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BaseFormat {
+    Alpha,
+    Luminance,
+    LuminanceAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl BaseFormat {
+    /// Converts an RGBA color to a texel of this format, expanded back to RGBA.
+    pub fn convert(self, [r, g, b, a]: [f32; 4]) -> Vec4 {
+        match self {
+            BaseFormat::Alpha => Vec4::new(0.0, 0.0, 0.0, a),
+            BaseFormat::Luminance => Vec4::new(r, r, r, 1.0),
+            BaseFormat::LuminanceAlpha => Vec4::new(r, r, r, a),
+            BaseFormat::Rgb => Vec4::new(r, g, b, 1.0),
+            BaseFormat::Rgba => Vec4::new(r, g, b, a),
+        }
+    }
+
+    fn has_color(self) -> bool {
+        self != BaseFormat::Alpha
+    }
+
+    fn has_alpha(self) -> bool {
+        matches!(
+            self,
+            BaseFormat::Alpha | BaseFormat::LuminanceAlpha | BaseFormat::Rgba
+        )
+    }
+}
+
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub base_format: BaseFormat,
+    pub data: Vec<Vec4>,
+    pub priority: f32,
+}
+
+impl Default for Texture {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            base_format: BaseFormat::Rgba,
+            data: Vec::new(),
+            priority: 1.0,
+        }
+    }
+}
+
+impl Texture {
+    pub fn is_complete(&self) -> bool {
+        self.width != 0 && self.height != 0
+    }
+
+    /// Looks up the texel nearest to `tex_coord`, dividing by q and wrapping with GL_REPEAT.
+    pub fn sample(&self, tex_coord: Vec4) -> Vec4 {
+        let (s, t) = (tex_coord.x / tex_coord.w, tex_coord.y / tex_coord.w);
+        let x = (s.rem_euclid(1.0) * self.width as f32) as usize % self.width;
+        let y = (t.rem_euclid(1.0) * self.height as f32) as usize % self.height;
+        self.data[x + y * self.width]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TexEnvMode {
+    Modulate,
+    Replace,
+    Decal,
+    Blend,
+    Add,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TexEnv {
+    pub mode: TexEnvMode,
+    pub color: Vec4,
+}
+
+impl Default for TexEnv {
+    fn default() -> Self {
+        Self {
+            mode: TexEnvMode::Modulate,
+            color: Vec4::zero(),
+        }
+    }
+}
+
+impl TexEnv {
+    /// Combines the incoming fragment color with a texel from a texture of format `base`.
+    pub fn apply(&self, base: BaseFormat, fragment: Vec4, texel: Vec4) -> Vec4 {
+        let (cf, ct) = (fragment.xyz(), texel.xyz());
+        let (af, at) = (fragment.w, texel.w);
+
+        let modulated_alpha = if base.has_alpha() { af * at } else { af };
+        let (c, a) = match self.mode {
+            TexEnvMode::Replace => (
+                if base.has_color() { ct } else { cf },
+                if base.has_alpha() { at } else { af },
+            ),
+            TexEnvMode::Modulate => (
+                if base.has_color() {
+                    Vec3::new(cf.x * ct.x, cf.y * ct.y, cf.z * ct.z)
+                } else {
+                    cf
+                },
+                modulated_alpha,
+            ),
+            TexEnvMode::Decal => match base {
+                BaseFormat::Rgb => (ct, af),
+                BaseFormat::Rgba => (cf * (1.0 - at) + ct * at, af),
+                // undefined by the spec
+                _ => (cf, af),
+            },
+            TexEnvMode::Blend => (
+                if base.has_color() {
+                    let cc = self.color.xyz();
+                    Vec3::new(
+                        cf.x * (1.0 - ct.x) + cc.x * ct.x,
+                        cf.y * (1.0 - ct.y) + cc.y * ct.y,
+                        cf.z * (1.0 - ct.z) + cc.z * ct.z,
+                    )
+                } else {
+                    cf
+                },
+                modulated_alpha,
+            ),
+            TexEnvMode::Add => (
+                if base.has_color() {
+                    let sum = cf + ct;
+                    Vec3::new(sum.x.min(1.0), sum.y.min(1.0), sum.z.min(1.0))
+                } else {
+                    cf
+                },
+                modulated_alpha,
+            ),
+        };
+
+        Vec4::new(c.x, c.y, c.z, a)
+    }
+}
+
+/// Per-unit texturing state.
+pub struct TextureUnit {
+    pub enabled: bool,
+    pub bound_texture: u32,
+    pub env: TexEnv,
+    pub matrix_stack: Vec<Mat4>,
+    /// The current texture coordinates given to new vertices.
+    pub tex_coord: Vec4,
+}
+
+impl Default for TextureUnit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bound_texture: 0,
+            env: Default::default(),
+            matrix_stack: vec![Mat4::identity()],
+            tex_coord: Vec4::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+}
+
+/// Texture objects keyed by name.
+///
+/// Names handed out by glGenTextures are reserved but have no object until they are first bound,
+/// which is what glIsTexture distinguishes. Name 0 is the default texture and always exists.
+pub struct Textures {
+    objects: HashMap<u32, Option<Texture>>,
+    next_name: u32,
+}
+
+impl Default for Textures {
+    fn default() -> Self {
+        Self {
+            objects: HashMap::from([(0, Some(Default::default()))]),
+            next_name: 1,
+        }
+    }
+}
+
+impl Textures {
+    /// Reserves a name that is not in use.
+    pub fn generate(&mut self) -> u32 {
+        while self.next_name == 0 || self.objects.contains_key(&self.next_name) {
+            self.next_name = self.next_name.wrapping_add(1);
+        }
+        let name = self.next_name;
+        self.objects.insert(name, None);
+        self.next_name = self.next_name.wrapping_add(1);
+        name
+    }
+
+    pub fn get(&self, name: u32) -> Option<&Texture> {
+        self.objects.get(&name)?.as_ref()
+    }
+
+    /// Returns the object for `name`, creating it if this is the first time it is bound.
+    pub fn get_or_create(&mut self, name: u32) -> &mut Texture {
+        self.objects
+            .entry(name)
+            .or_default()
+            .get_or_insert_with(Default::default)
+    }
+
+    /// Frees the object and its name, unless it is the default texture.
+    pub fn delete(&mut self, name: u32) {
+        if name != 0 {
+            self.objects.remove(&name);
+        }
+    }
+
+    /// Returns whether any names other than the default texture's are in use.
+    pub fn is_empty(&self) -> bool {
+        self.objects.len() == 1
+    }
+
+    pub fn is_texture(&self, name: u32) -> bool {
+        name != 0 && self.get(name).is_some()
+    }
+}
@@ -0,0 +1,568 @@
+//! Recording GL and WGL calls to a binary trace file, to see exactly what an application asked
+//! for when it renders incorrectly.
+//!
+//! Tracing is turned on by setting `MINIGL_TRACE` to the path of the file to write. When it is off,
+//! each call only pays for checking that it is. Records are buffered, and written out whenever a
+//! frame ends with a swap, glFlush or glFinish.
+//!
+//! A trace starts with the magic bytes `MGLTRACE` and a u32 format version, followed by one record
+//! per call. Everything is little endian. Each record is:
+//!
+//! - u16: the call, as its position in the `Call` list
+//! - u32: the thread that made the call, numbered from 0 in the order threads first made one
+//! - u32: the size of the arguments in bytes
+//! - the arguments, in order
+//!
+//! Scalars are stored at their own size, booleans as one byte and handles as u64. Pointed-to data
+//! is stored as a u32 element count followed by the elements, with a count of `u32::MAX` for a
+//! null pointer. Calls that create objects or depend on the window system also store what they
+//! returned or found out after their arguments, as documented where they are recorded.
+//!
+//! Calls that minigl makes internally, like glColor3f calling glColor4f, are not recorded, so a
+//! trace has exactly the calls the application made.
+
+use std::{
+    cell::Cell,
+    env,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use crate::PrimitiveMode;
+
+pub const MAGIC: &[u8; 8] = b"MGLTRACE";
+pub const VERSION: u32 = 1;
+
+/// The element count stored for a null pointer.
+pub const NULL: u32 = u32::MAX;
+
+macro_rules! calls {
+    ($($call:ident),* $(,)?) => {
+        /// Every call that can appear in a trace. Their positions are written to traces, so new
+        /// calls have to go at the end.
+        #[repr(u16)]
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum Call {
+            $($call),*
+        }
+
+        impl Call {
+            pub fn from_id(id: u16) -> Option<Self> {
+                const CALLS: &[Call] = &[$(Call::$call),*];
+                CALLS.get(id as usize).copied()
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Call::$call => stringify!($call)),*
+                }
+            }
+        }
+    };
+}
+
+calls! {
+    mglSetGammaRamp, mglGetGammaRamp, mglSetDrawableSize, mglAddFrameCallback,
+    mglRemoveFrameCallback, glFlush, glFinish, glGetString, glClearColor, glClearDepth,
+    glClearStencil, glClear, glScissor, glColorMask, glStencilMask, glCullFace, glEnable, glDisable,
+    glAlphaFunc, glBlendFunc, glDepthFunc, glDepthRange, glDepthMask, glPolygonMode, glShadeModel,
+    glTexParameterf, glTexEnvi, glTexEnvf, glTexEnvfv, glTexEnviv, glActiveTextureARB,
+    glClientActiveTextureARB, glMultiTexCoord4fARB, glMultiTexCoord1fARB, glMultiTexCoord2fARB,
+    glMultiTexCoord3fARB, glMultiTexCoord2fvARB, glMultiTexCoord3fvARB, glMultiTexCoord4fvARB,
+    glSelectTextureSGIS, glMTexCoord2fSGIS, glMTexCoord2fvSGIS, glBindTexture, glGenTextures,
+    glDeleteTextures, glIsTexture, glAreTexturesResident, glPrioritizeTextures, glTexImage2D,
+    glViewport, glMatrixMode, glLoadIdentity, glOrtho, glFrustum, glColor3f, glColor3ubv, glColor4f,
+    glColor4fv, glBegin, glEnd, glTexCoord2f, glVertex2f, glVertex3f, glVertex3fv, glVertex4f,
+    glDrawBuffer, glReadBuffer, glRotatef, glTranslatef, glScalef, glGetFloatv, glGetIntegerv,
+    glPushMatrix, glPopMatrix, glPixelStorei, glPixelStoref, glReadPixels, glRasterPos2i,
+    glRasterPos2f, glRasterPos2d, glRasterPos3i, glRasterPos3f, glRasterPos3d, glRasterPos2fv,
+    glRasterPos3fv, glRasterPos4f, glPixelZoom, glDrawPixels, glCopyPixels, glBitmap,
+    glTexSubImage2D, wglCreateContext, wglMakeCurrent, wglGetCurrentContext, wglGetCurrentDC,
+    wglDeleteContext, wglShareLists, wglGetProcAddress, wglChoosePixelFormat,
+    wglDescribePixelFormat, wglGetPixelFormat, wglSetPixelFormat, wglSwapBuffers,
+    wglSwapIntervalEXT, wglGetSwapIntervalEXT, wglSetDeviceGammaRamp3DFX, wglGetDeviceGammaRamp3DFX,
+}
+
+/// Records the enclosing entry point and its arguments, if tracing is on. The record is written
+/// when the enclosing block ends, so that calls made on the way are recognized as internal.
+macro_rules! trace {
+    ($call:ident $(, $arg:expr)* $(,)?) => {
+        let _trace = $crate::trace::begin($crate::trace::Call::$call).map(|trace| trace$(.arg($arg))*);
+    };
+}
+
+/// A value that can be stored as a call argument.
+pub(crate) trait Arg {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+macro_rules! scalar_args {
+    ($($type:ty),*) => {
+        $(
+            impl Arg for $type {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+scalar_args!(u8, u16, u32, u64, i32, f32, f64);
+
+impl Arg for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+/// Handles and addresses, stored as u64 whatever the pointer size.
+impl Arg for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Arg for PrimitiveMode {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out);
+    }
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+}
+
+impl<T: Arg, const N: usize> Arg for [T; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for value in self.iter() {
+            value.encode(out);
+        }
+    }
+}
+
+/// Pointed-to data, or `None` for a null pointer.
+impl<T: Arg> Arg for Option<&[T]> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(values) => {
+                (values.len() as u32).encode(out);
+                for value in values.iter() {
+                    value.encode(out);
+                }
+            }
+            None => NULL.encode(out),
+        }
+    }
+}
+
+/// Returns the `len` values at `ptr`, or `None` if it is null.
+///
+/// # Safety
+///
+/// Unless it is null, `ptr` must point to `len` initialized values.
+pub(crate) unsafe fn slice<'a, T>(ptr: *const T, len: usize) -> Option<&'a [T]> {
+    (!ptr.is_null()).then(|| unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+struct Tracer {
+    out: BufWriter<File>,
+    /// Set once writing has failed, after which nothing more is written.
+    failed: bool,
+}
+
+fn tracer() -> Option<&'static Mutex<Tracer>> {
+    static TRACER: OnceLock<Option<Mutex<Tracer>>> = OnceLock::new();
+    TRACER
+        .get_or_init(|| {
+            let path = env::var_os("MINIGL_TRACE")?;
+            let file = match File::create(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    // the user asked for a trace, so failing silently would only confuse them
+                    let path = std::path::Path::new(&path).display();
+                    eprintln!("minigl: cannot trace to {path}: {err}");
+                    return None;
+                }
+            };
+            let mut out = BufWriter::new(file);
+            let failed = out
+                .write_all(MAGIC)
+                .and_then(|()| out.write_all(&VERSION.to_le_bytes()))
+                .is_err();
+            Some(Mutex::new(Tracer { out, failed }))
+        })
+        .as_ref()
+}
+
+thread_local! {
+    /// Whether this thread is inside a traced call.
+    static IN_CALL: Cell<bool> = const { Cell::new(false) };
+    static THREAD: u32 = {
+        static NEXT_THREAD: AtomicU32 = AtomicU32::new(0);
+        NEXT_THREAD.fetch_add(1, Ordering::Relaxed)
+    };
+}
+
+/// A call being recorded, which is written to the trace when dropped.
+pub(crate) struct Recording {
+    call: Call,
+    args: Vec<u8>,
+}
+
+/// Starts recording a call, unless tracing is off or the call is made from inside another one.
+pub(crate) fn begin(call: Call) -> Option<Recording> {
+    tracer()?;
+    if IN_CALL.with(|in_call| in_call.replace(true)) {
+        return None;
+    }
+    Some(Recording {
+        call,
+        args: Vec::new(),
+    })
+}
+
+impl Recording {
+    pub fn arg(mut self, value: impl Arg) -> Self {
+        self.push(value);
+        self
+    }
+
+    /// Adds a value found out while making the call, after the arguments.
+    pub fn push(&mut self, value: impl Arg) {
+        value.encode(&mut self.args);
+    }
+
+    /// Writes the call as one of `thread`'s records.
+    fn write_to(&self, thread: u32, out: &mut impl Write) -> io::Result<()> {
+        let mut header = [0; 10];
+        header[..2].copy_from_slice(&(self.call as u16).to_le_bytes());
+        header[2..6].copy_from_slice(&thread.to_le_bytes());
+        header[6..].copy_from_slice(&(self.args.len() as u32).to_le_bytes());
+        out.write_all(&header)?;
+        out.write_all(&self.args)
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        IN_CALL.with(|in_call| in_call.set(false));
+        let Some(tracer) = tracer() else {
+            return;
+        };
+        let mut tracer = tracer.lock().unwrap_or_else(|e| e.into_inner());
+        if tracer.failed {
+            return;
+        }
+
+        let ends_frame = matches!(
+            self.call,
+            Call::wglSwapBuffers | Call::glFlush | Call::glFinish
+        );
+        let out = &mut tracer.out;
+        let result = self
+            .write_to(THREAD.with(|thread| *thread), out)
+            .and_then(|()| if ends_frame { out.flush() } else { Ok(()) });
+        if let Err(err) = result {
+            eprintln!("minigl: cannot write trace: {err}");
+            tracer.failed = true;
+        }
+    }
+}
+
+/// A value that can be read back from a call's arguments.
+pub trait Decode: Sized {
+    fn decode(args: &mut Args) -> Option<Self>;
+}
+
+macro_rules! scalar_decodes {
+    ($($type:ty),*) => {
+        $(
+            impl Decode for $type {
+                fn decode(args: &mut Args) -> Option<Self> {
+                    let bytes = args.take(std::mem::size_of::<$type>())?;
+                    Some(<$type>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+scalar_decodes!(u8, u16, u32, u64, i32, f32, f64);
+
+impl Decode for bool {
+    fn decode(args: &mut Args) -> Option<Self> {
+        u8::decode(args).map(|value| value != 0)
+    }
+}
+
+impl Decode for PrimitiveMode {
+    fn decode(args: &mut Args) -> Option<Self> {
+        use PrimitiveMode::*;
+        const MODES: [PrimitiveMode; 10] = [
+            Points,
+            Lines,
+            LineStrip,
+            LineLoop,
+            Triangles,
+            TriangleStrip,
+            TriangleFan,
+            Quads,
+            QuadStrip,
+            Polygon,
+        ];
+        MODES.get(u32::decode(args)? as usize).copied()
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(args: &mut Args) -> Option<Self> {
+        let values: Vec<T> = (0..N).map(|_| T::decode(args)).collect::<Option<_>>()?;
+        values.try_into().ok()
+    }
+}
+
+/// Pointed-to data, or `None` for a null pointer.
+impl<T: Decode> Decode for Option<Vec<T>> {
+    fn decode(args: &mut Args) -> Option<Self> {
+        let count = u32::decode(args)?;
+        if count == NULL {
+            return Some(None);
+        }
+        // the count comes from the file, so check it against what is left before allocating
+        if count as usize > args.data.len() {
+            return None;
+        }
+        let values = (0..count).map(|_| T::decode(args)).collect::<Option<_>>()?;
+        Some(Some(values))
+    }
+}
+
+/// The arguments of a recorded call, read in order.
+pub struct Args<'a> {
+    data: &'a [u8],
+}
+
+impl Args<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+
+    /// Reads the next argument, failing if the record is too short to hold it.
+    pub fn get<T: Decode>(&mut self) -> io::Result<T> {
+        T::decode(self)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated call record"))
+    }
+}
+
+/// One call read from a trace.
+pub struct Record {
+    /// The position of the call in the `Call` list, which may be past the end of it if the trace
+    /// was written by a newer minigl.
+    pub id: u16,
+    pub thread: u32,
+    pub args: Vec<u8>,
+}
+
+impl Record {
+    pub fn call(&self) -> Option<Call> {
+        Call::from_id(self.id)
+    }
+
+    pub fn args(&self) -> Args<'_> {
+        Args { data: &self.args }
+    }
+}
+
+/// Reads the records of a trace in order.
+pub struct Reader<R> {
+    input: R,
+}
+
+impl<R: Read> Reader<R> {
+    /// Checks that `input` starts with the header of a trace this version of minigl can read.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 12];
+        input.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a minigl trace",
+            ));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported trace version {version}"),
+            ));
+        }
+        Ok(Self { input })
+    }
+
+    /// Returns the next record, or `None` at the end of the trace.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut header = [0; 10];
+        // a trace cut off by a crash just ends early
+        match self.input.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let id = u16::from_le_bytes(header[..2].try_into().unwrap());
+        let thread = u32::from_le_bytes(header[2..6].try_into().unwrap());
+        let len = u32::from_le_bytes(header[6..].try_into().unwrap());
+
+        let mut args = Vec::new();
+        (&mut self.input).take(len as u64).read_to_end(&mut args)?;
+        if args.len() != len as usize {
+            return Ok(None);
+        }
+        Ok(Some(Record { id, thread, args }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(call: Call) -> Recording {
+        Recording {
+            call,
+            args: Vec::new(),
+        }
+    }
+
+    /// Returns a trace of `recordings`, made by thread 3.
+    fn write_trace(recordings: &[Recording]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        for recording in recordings {
+            recording.write_to(3, &mut out).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn calls_decode_to_what_was_encoded() {
+        let color = [0.5, 0.25, 1.0, 0.75];
+        let trace = write_trace(&[
+            recording(Call::glClearColor)
+                .arg(0.25f32)
+                .arg(0.5f32)
+                .arg(0.75f32)
+                .arg(1.0f32),
+            recording(Call::glBegin).arg(PrimitiveMode::TriangleFan),
+            recording(Call::glTexEnvfv)
+                .arg(0x2300u32)
+                .arg(0x2201u32)
+                .arg(Some(&color[..])),
+            recording(Call::glDeleteTextures)
+                .arg(2i32)
+                .arg(None::<&[u32]>),
+            recording(Call::glDepthMask).arg(true),
+        ]);
+
+        let mut reader = Reader::new(&trace[..]).unwrap();
+        let mut next = |call| {
+            let record = reader.next_record().unwrap().unwrap();
+            assert_eq!(record.call(), Some(call));
+            assert_eq!(record.thread, 3);
+            record
+        };
+
+        let record = next(Call::glClearColor);
+        let mut args = record.args();
+        let values: [f32; 4] = args.get().unwrap();
+        assert_eq!(values, [0.25, 0.5, 0.75, 1.0]);
+        assert!(args.get::<u8>().is_err());
+
+        let record = next(Call::glBegin);
+        let mode: PrimitiveMode = record.args().get().unwrap();
+        assert_eq!(mode, PrimitiveMode::TriangleFan);
+
+        let record = next(Call::glTexEnvfv);
+        let mut args = record.args();
+        let (target, pname): (u32, u32) = (args.get().unwrap(), args.get().unwrap());
+        assert_eq!((target, pname), (0x2300, 0x2201));
+        let params: Option<Vec<f32>> = args.get().unwrap();
+        assert_eq!(params.as_deref(), Some(&color[..]));
+
+        let record = next(Call::glDeleteTextures);
+        let mut args = record.args();
+        assert_eq!(args.get::<i32>().unwrap(), 2);
+        assert_eq!(args.get::<Option<Vec<u32>>>().unwrap(), None);
+
+        let record = next(Call::glDepthMask);
+        assert!(record.args().get::<bool>().unwrap());
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        assert!(Reader::new(&b"MGLTRACE"[..]).is_err());
+        assert!(Reader::new(&b"NOTATRACE\x01\0\0"[..]).is_err());
+        let mut newer = MAGIC.to_vec();
+        newer.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(Reader::new(&newer[..]).is_err());
+    }
+
+    #[test]
+    fn truncated_records_end_the_trace() {
+        let mut trace = write_trace(&[
+            recording(Call::glFlush),
+            recording(Call::glClearDepth).arg(1.0f64),
+        ]);
+        trace.truncate(trace.len() - 3);
+
+        let mut reader = Reader::new(&trace[..]).unwrap();
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.call(), Some(Call::glFlush));
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_lengths_are_rejected() {
+        // a record claiming more arguments than the file has
+        let mut trace = write_trace(&[recording(Call::glFlush)]);
+        let len = trace.len();
+        trace[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        trace.extend_from_slice(&[0; 16]);
+        let mut reader = Reader::new(&trace[..]).unwrap();
+        assert!(reader.next_record().unwrap().is_none());
+
+        // pointed-to data claiming more elements than the record has
+        let trace = write_trace(&[recording(Call::mglSetGammaRamp).arg(1000u32).arg([0u16; 4])]);
+        let record = Reader::new(&trace[..])
+            .unwrap()
+            .next_record()
+            .unwrap()
+            .unwrap();
+        let err = record.args().get::<Option<Vec<u16>>>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // arguments the record is too short to hold
+        let trace = write_trace(&[recording(Call::glClearColor).arg(1.0f32)]);
+        let record = Reader::new(&trace[..])
+            .unwrap()
+            .next_record()
+            .unwrap()
+            .unwrap();
+        let mut args = record.args();
+        assert_eq!(args.get::<f32>().unwrap(), 1.0);
+        assert!(args.get::<f32>().is_err());
+    }
+}
@@ -0,0 +1,357 @@
+//! The WGL front end, which presents to windows with GDI.
+
+use std::{cell::Cell, collections::BTreeMap, ffi::c_void, sync::Mutex};
+
+use crate::{
+    context::{self, Context},
+    format::{self, FormatRequest, PixelFormat},
+    mglGetGammaRamp, mglSetGammaRamp,
+    presenter::Presenter,
+    procs,
+    trace::{self, Call},
+    win32, GLState, GLushort,
+};
+
+/// The 1-based pixel format set on each window, keyed by HWND.
+static WINDOW_FORMATS: Mutex<BTreeMap<usize, i32>> = Mutex::new(BTreeMap::new());
+
+fn window_format(hdc: win32::HDC) -> Option<i32> {
+    let hwnd = unsafe { win32::WindowFromDC(hdc) } as usize;
+    let formats = WINDOW_FORMATS.lock().unwrap_or_else(|e| e.into_inner());
+    formats.get(&hwnd).copied()
+}
+
+fn pixel_format(index: i32) -> Option<PixelFormat> {
+    let index = usize::try_from(index).ok()?.checked_sub(1)?;
+    format::supported().nth(index)
+}
+
+#[no_mangle]
+pub extern "system" fn wglCreateContext(hdc: win32::HDC) -> win32::HGLRC {
+    let mut recording = trace::begin(Call::wglCreateContext).map(|trace| trace.arg(hdc));
+
+    // real implementations fail here, but older versions of minigl did not care whether a format
+    // was set, so fall back to the default one
+    let index = window_format(hdc);
+    let format = index.and_then(pixel_format).unwrap_or_default();
+
+    let (width, height) = client_size(hdc);
+    let hglrc = context::create(Context::new(GLState::new(width, height, format)));
+    // replays have no window to find these out from
+    if let Some(recording) = &mut recording {
+        recording.push(hglrc);
+        recording.push(index.unwrap_or(0));
+        recording.push(width as u32);
+        recording.push(height as u32);
+    }
+    hglrc
+}
+
+/// Returns the size of the client area of the window `hdc` belongs to.
+fn client_size(hdc: win32::HDC) -> (usize, usize) {
+    let mut rect: win32::RECT = Default::default();
+    unsafe {
+        win32::GetClientRect(win32::WindowFromDC(hdc), &mut rect as _);
+    }
+    (
+        (rect.right - rect.left).max(0) as usize,
+        (rect.bottom - rect.top).max(0) as usize,
+    )
+}
+
+/// Presents to a window with GDI.
+struct WindowPresenter {
+    hdc: win32::HDC,
+}
+
+impl Presenter for WindowPresenter {
+    fn size(&self) -> Option<(usize, usize)> {
+        Some(client_size(self.hdc))
+    }
+
+    fn present(&mut self, pixels: &[u8], width: usize, height: usize) {
+        let bmi = win32::BITMAPINFOHEADER {
+            size: std::mem::size_of::<win32::BITMAPINFOHEADER>() as u32,
+            width: width as i32,
+            // positive heights are bottom-up, which matches GL
+            height: height as i32,
+            planes: 1,
+            bit_count: 32,
+            compression: win32::BI_RGB,
+            ..Default::default()
+        };
+
+        // the window may have been resized since the frame was started, so stretch what was
+        // drawn over the whole window
+        let (window_width, window_height) = client_size(self.hdc);
+        unsafe {
+            win32::StretchDIBits(
+                self.hdc,
+                0,
+                0,
+                window_width as i32,
+                window_height as i32,
+                0,
+                0,
+                width as i32,
+                height as i32,
+                pixels.as_ptr() as *const c_void,
+                &bmi as _,
+                win32::DIB_RGB_COLORS,
+                win32::SRCCOPY,
+            );
+        }
+    }
+}
+
+thread_local! {
+    /// The DC passed to the last successful wglMakeCurrent on this thread.
+    static CURRENT_DC: Cell<win32::HDC> = const { Cell::new(0) };
+}
+
+#[no_mangle]
+pub extern "system" fn wglMakeCurrent(hdc: win32::HDC, hglrc: win32::HGLRC) -> win32::BOOL {
+    // replays have no window to find out its size from
+    trace!(wglMakeCurrent, hdc, hglrc, {
+        let (width, height) = client_size(hdc);
+        [width as u32, height as u32]
+    });
+    if !context::make_current(hglrc) {
+        return false;
+    }
+    if hglrc != 0 {
+        context::with_current(|context| context.set_presenter(Box::new(WindowPresenter { hdc })));
+    }
+    CURRENT_DC.with(|current| current.set(hdc));
+    true
+}
+
+#[no_mangle]
+pub extern "system" fn wglGetCurrentContext() -> win32::HGLRC {
+    trace!(wglGetCurrentContext);
+    context::current().unwrap_or(0)
+}
+
+#[no_mangle]
+pub extern "system" fn wglGetCurrentDC() -> win32::HDC {
+    trace!(wglGetCurrentDC);
+    // deleting the current context releases it without going through wglMakeCurrent
+    match context::current() {
+        Some(_) => CURRENT_DC.with(|current| current.get()),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn wglDeleteContext(hglrc: win32::HGLRC) -> win32::BOOL {
+    trace!(wglDeleteContext, hglrc);
+    context::delete(hglrc)
+}
+
+#[no_mangle]
+pub extern "system" fn wglShareLists(hglrc1: win32::HGLRC, hglrc2: win32::HGLRC) -> win32::BOOL {
+    trace!(wglShareLists, hglrc1, hglrc2);
+    let Some(namespace) = context::with_context(hglrc1, |context| context.state.namespace.clone())
+    else {
+        return false;
+    };
+    context::with_context(hglrc2, |context| {
+        let state = &mut context.state;
+        if state.namespace.ptr_eq(&namespace) {
+            return true;
+        }
+        // like other implementations, refuse to throw away objects the context already created
+        if !state.namespace.lock().textures.is_empty() {
+            return false;
+        }
+        state.namespace = namespace;
+        true
+    })
+    .unwrap_or(false)
+}
+
+#[no_mangle]
+pub extern "system" fn wglGetProcAddress(proc: win32::LPCSTR) -> win32::PROC {
+    if proc.is_null() {
+        trace!(wglGetProcAddress, None::<&[u8]>);
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(proc as *const std::ffi::c_char) };
+    trace!(wglGetProcAddress, Some(name.to_bytes()));
+    procs::lookup(name.to_bytes()).map(|address| unsafe { std::mem::transmute(address) })
+}
+
+#[no_mangle]
+pub extern "system" fn wglChoosePixelFormat(
+    _hdc: win32::HDC,
+    ppfd: *const win32::PIXELFORMATDESCRIPTOR,
+) -> i32 {
+    trace!(wglChoosePixelFormat, _hdc, unsafe {
+        let size = std::mem::size_of::<win32::PIXELFORMATDESCRIPTOR>();
+        trace::slice(ppfd as *const u8, size)
+    });
+    let Some(pfd) = (unsafe { ppfd.as_ref() }) else {
+        return 0;
+    };
+    if pfd.pixel_type != win32::PFD_TYPE_RGBA {
+        return 0;
+    }
+
+    let request = FormatRequest {
+        color_bits: pfd.color_bits,
+        alpha_bits: pfd.alpha_bits,
+        depth_bits: (pfd.flags & win32::PFD_DEPTH_DONTCARE == 0).then_some(pfd.depth_bits),
+        stencil_bits: pfd.stencil_bits,
+        double_buffer: (pfd.flags & win32::PFD_DOUBLEBUFFER_DONTCARE == 0)
+            .then_some(pfd.flags & win32::PFD_DOUBLEBUFFER != 0),
+        swap_copy: if pfd.flags & win32::PFD_SWAP_COPY != 0 {
+            Some(true)
+        } else if pfd.flags & win32::PFD_SWAP_EXCHANGE != 0 {
+            Some(false)
+        } else {
+            None
+        },
+    };
+    format::choose(&request) as i32 + 1
+}
+
+#[no_mangle]
+pub extern "system" fn wglDescribePixelFormat(
+    _hdc: win32::HDC,
+    format: i32,
+    bytes: u32,
+    ppfd: *mut win32::PIXELFORMATDESCRIPTOR,
+) -> i32 {
+    trace!(wglDescribePixelFormat, _hdc, format, bytes);
+    let count = format::supported().count() as i32;
+    if ppfd.is_null() {
+        return count;
+    }
+    let Some(format) = pixel_format(format) else {
+        return 0;
+    };
+
+    let mut flags = win32::PFD_DRAW_TO_WINDOW | win32::PFD_SUPPORT_OPENGL;
+    if format.double_buffer {
+        flags |= win32::PFD_DOUBLEBUFFER;
+        flags |= if format.swap_copy {
+            win32::PFD_SWAP_COPY
+        } else {
+            win32::PFD_SWAP_EXCHANGE
+        };
+    }
+    let pfd = win32::PIXELFORMATDESCRIPTOR {
+        size: std::mem::size_of::<win32::PIXELFORMATDESCRIPTOR>() as u16,
+        version: 1,
+        flags,
+        pixel_type: win32::PFD_TYPE_RGBA,
+        color_bits: format.color_bits,
+        // the color buffer is BGRA
+        red_bits: 8,
+        red_shift: 16,
+        green_bits: 8,
+        green_shift: 8,
+        blue_bits: 8,
+        blue_shift: 0,
+        alpha_bits: format.alpha_bits,
+        alpha_shift: if format.alpha_bits > 0 { 24 } else { 0 },
+        depth_bits: format.depth_bits,
+        stencil_bits: format.stencil_bits,
+        layer_type: win32::PFD_MAIN_PLANE,
+        ..Default::default()
+    };
+
+    // callers may pass a smaller struct from an older SDK, so only copy what fits
+    let size = (bytes as usize).min(std::mem::size_of::<win32::PIXELFORMATDESCRIPTOR>());
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            &pfd as *const win32::PIXELFORMATDESCRIPTOR as *const u8,
+            ppfd as *mut u8,
+            size,
+        );
+    }
+    count
+}
+
+#[no_mangle]
+pub extern "system" fn wglGetPixelFormat(hdc: win32::HDC) -> i32 {
+    trace!(wglGetPixelFormat, hdc);
+    window_format(hdc).unwrap_or(0)
+}
+
+#[no_mangle]
+pub extern "system" fn wglSetPixelFormat(
+    hdc: win32::HDC,
+    format: i32,
+    _ppfd: *const win32::PIXELFORMATDESCRIPTOR,
+) -> win32::BOOL {
+    trace!(wglSetPixelFormat, hdc, format);
+    if pixel_format(format).is_none() {
+        return false;
+    }
+    let hwnd = unsafe { win32::WindowFromDC(hdc) } as usize;
+    let mut formats = WINDOW_FORMATS.lock().unwrap_or_else(|e| e.into_inner());
+    // like GDI, a window's format can only be set once
+    match formats.get(&hwnd) {
+        Some(&existing) => existing == format,
+        None => {
+            formats.insert(hwnd, format);
+            true
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn wglSwapBuffers(hdc: win32::HDC) -> win32::BOOL {
+    // the window may have been resized, which replays have no other way of knowing
+    trace!(wglSwapBuffers, hdc, {
+        let (width, height) = client_size(hdc);
+        [width as u32, height as u32]
+    });
+    // the DC has to belong to the window the current context is bound to, which is where the
+    // context presents anyway
+    context::swap_current();
+    true
+}
+
+#[no_mangle]
+pub extern "system" fn wglSwapIntervalEXT(interval: i32) -> win32::BOOL {
+    trace!(wglSwapIntervalEXT, interval);
+    // negative intervals are for adaptive vsync, which needs a real display
+    let Ok(interval) = u32::try_from(interval) else {
+        return false;
+    };
+    if context::current().is_none() {
+        return false;
+    }
+    context::with_current(|context| context.pacer.interval = interval);
+    true
+}
+
+#[no_mangle]
+pub extern "system" fn wglGetSwapIntervalEXT() -> i32 {
+    trace!(wglGetSwapIntervalEXT);
+    context::with_current(|context| context.pacer.interval as i32)
+}
+
+#[no_mangle]
+pub extern "system" fn wglSetDeviceGammaRamp3DFX(
+    _hdc: win32::HDC,
+    ramp: *const c_void,
+) -> win32::BOOL {
+    trace!(wglSetDeviceGammaRamp3DFX, _hdc, unsafe {
+        trace::slice(ramp as *const GLushort, 3 * 256)
+    });
+    mglSetGammaRamp(ramp as *const GLushort);
+    !ramp.is_null()
+}
+
+#[no_mangle]
+pub extern "system" fn wglGetDeviceGammaRamp3DFX(
+    _hdc: win32::HDC,
+    ramp: *mut c_void,
+) -> win32::BOOL {
+    trace!(wglGetDeviceGammaRamp3DFX, _hdc);
+    mglGetGammaRamp(ramp as *mut GLushort);
+    !ramp.is_null()
+}
@@ -0,0 +1,199 @@
+#![allow(clippy::upper_case_acronyms, non_camel_case_types, non_snake_case)]
+
+use std::ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+
+pub type Bool = c_int;
+pub type Display = c_void;
+pub type Visual = c_void;
+pub type XID = c_ulong;
+pub type Drawable = XID;
+pub type VisualID = c_ulong;
+pub type GC = *mut c_void;
+pub type ShmSeg = c_ulong;
+
+pub const FALSE: Bool = 0;
+pub const TRUE: Bool = 1;
+
+pub const VISUAL_SCREEN_MASK: c_long = 0x2;
+pub const VISUAL_DEPTH_MASK: c_long = 0x4;
+pub const VISUAL_CLASS_MASK: c_long = 0x8;
+pub const TRUE_COLOR: c_int = 4;
+
+pub const Z_PIXMAP: c_int = 2;
+pub const LSB_FIRST: c_int = 0;
+
+#[repr(C)]
+pub struct XVisualInfo {
+    pub visual: *mut Visual,
+    pub visualid: VisualID,
+    pub screen: c_int,
+    pub depth: c_int,
+    pub class: c_int,
+    pub red_mask: c_ulong,
+    pub green_mask: c_ulong,
+    pub blue_mask: c_ulong,
+    pub colormap_size: c_int,
+    pub bits_per_rgb: c_int,
+}
+
+#[repr(C)]
+pub struct XWindowAttributes {
+    pub x: c_int,
+    pub y: c_int,
+    pub width: c_int,
+    pub height: c_int,
+    pub border_width: c_int,
+    pub depth: c_int,
+    pub visual: *mut Visual,
+    pub root: XID,
+    pub class: c_int,
+    pub bit_gravity: c_int,
+    pub win_gravity: c_int,
+    pub backing_store: c_int,
+    pub backing_planes: c_ulong,
+    pub backing_pixel: c_ulong,
+    pub save_under: Bool,
+    pub colormap: XID,
+    pub map_installed: Bool,
+    pub map_state: c_int,
+    pub all_event_masks: c_long,
+    pub your_event_mask: c_long,
+    pub do_not_propagate_mask: c_long,
+    pub override_redirect: Bool,
+    pub screen: *mut c_void,
+}
+
+#[repr(C)]
+pub struct XImage {
+    pub width: c_int,
+    pub height: c_int,
+    pub xoffset: c_int,
+    pub format: c_int,
+    pub data: *mut c_char,
+    pub byte_order: c_int,
+    pub bitmap_unit: c_int,
+    pub bitmap_bit_order: c_int,
+    pub bitmap_pad: c_int,
+    pub depth: c_int,
+    pub bytes_per_line: c_int,
+    pub bits_per_pixel: c_int,
+    pub red_mask: c_ulong,
+    pub green_mask: c_ulong,
+    pub blue_mask: c_ulong,
+    pub obdata: *mut c_char,
+    /// Function pointers that are only called through Xlib.
+    pub funcs: [*const c_void; 6],
+}
+
+#[repr(C)]
+pub struct XErrorEvent {
+    pub type_: c_int,
+    pub display: *mut Display,
+    pub resourceid: XID,
+    pub serial: c_ulong,
+    pub error_code: u8,
+    pub request_code: u8,
+    pub minor_code: u8,
+}
+
+#[repr(C)]
+pub struct XShmSegmentInfo {
+    pub shmseg: ShmSeg,
+    pub shmid: c_int,
+    pub shmaddr: *mut c_char,
+    pub read_only: Bool,
+}
+
+pub type XErrorHandler = Option<unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int>;
+
+#[link(name = "X11")]
+extern "C" {
+    pub fn XGetVisualInfo(
+        display: *mut Display,
+        mask: c_long,
+        template: *const XVisualInfo,
+        count: *mut c_int,
+    ) -> *mut XVisualInfo;
+    pub fn XGetWindowAttributes(
+        display: *mut Display,
+        window: XID,
+        attributes: *mut XWindowAttributes,
+    ) -> c_int;
+    pub fn XCreateGC(
+        display: *mut Display,
+        drawable: Drawable,
+        mask: c_ulong,
+        values: *mut c_void,
+    ) -> GC;
+    pub fn XFreeGC(display: *mut Display, gc: GC) -> c_int;
+    pub fn XCreateImage(
+        display: *mut Display,
+        visual: *mut Visual,
+        depth: c_uint,
+        format: c_int,
+        offset: c_int,
+        data: *mut c_char,
+        width: c_uint,
+        height: c_uint,
+        bitmap_pad: c_int,
+        bytes_per_line: c_int,
+    ) -> *mut XImage;
+    pub fn XDestroyImage(image: *mut XImage) -> c_int;
+    pub fn XPutImage(
+        display: *mut Display,
+        drawable: Drawable,
+        gc: GC,
+        image: *mut XImage,
+        src_x: c_int,
+        src_y: c_int,
+        dest_x: c_int,
+        dest_y: c_int,
+        width: c_uint,
+        height: c_uint,
+    ) -> c_int;
+    pub fn XSetErrorHandler(handler: XErrorHandler) -> XErrorHandler;
+    pub fn XFlush(display: *mut Display) -> c_int;
+    pub fn XSync(display: *mut Display, discard: Bool) -> c_int;
+}
+
+#[link(name = "Xext")]
+extern "C" {
+    pub fn XShmQueryExtension(display: *mut Display) -> Bool;
+    pub fn XShmCreateImage(
+        display: *mut Display,
+        visual: *mut Visual,
+        depth: c_uint,
+        format: c_int,
+        data: *mut c_char,
+        shminfo: *mut XShmSegmentInfo,
+        width: c_uint,
+        height: c_uint,
+    ) -> *mut XImage;
+    pub fn XShmAttach(display: *mut Display, shminfo: *mut XShmSegmentInfo) -> Bool;
+    pub fn XShmDetach(display: *mut Display, shminfo: *mut XShmSegmentInfo) -> Bool;
+    pub fn XShmPutImage(
+        display: *mut Display,
+        drawable: Drawable,
+        gc: GC,
+        image: *mut XImage,
+        src_x: c_int,
+        src_y: c_int,
+        dest_x: c_int,
+        dest_y: c_int,
+        width: c_uint,
+        height: c_uint,
+        send_event: Bool,
+    ) -> Bool;
+}
+
+pub const IPC_PRIVATE: c_int = 0;
+pub const IPC_CREAT: c_int = 0o1000;
+pub const IPC_RMID: c_int = 0;
+
+// System V shared memory from libc, for MIT-SHM
+extern "C" {
+    pub fn shmget(key: c_int, size: usize, flags: c_int) -> c_int;
+    pub fn shmat(id: c_int, address: *const c_void, flags: c_int) -> *mut c_void;
+    pub fn shmdt(address: *const c_void) -> c_int;
+    pub fn shmctl(id: c_int, command: c_int, buffer: *mut c_void) -> c_int;
+}
@@ -0,0 +1,207 @@
+//! Runs the GLX front end against a virtual X server. The test starts its own Xvfb, and is skipped
+//! when Xvfb is not installed.
+
+#![cfg(all(feature = "glx", target_os = "linux"))]
+
+use std::{
+    ffi::{c_char, c_int, c_uint, c_ulong, c_void, CString},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use opengl32::{glClear, glClearColor};
+
+const GLX_RGBA: c_int = 4;
+const GLX_DOUBLEBUFFER: c_int = 5;
+const GLX_DEPTH_SIZE: c_int = 12;
+const GLX_NONE: c_int = 0;
+
+const GL_COLOR_BUFFER_BIT: u32 = 0x4000;
+
+const Z_PIXMAP: c_int = 2;
+const ALL_PLANES: c_ulong = !0;
+
+type Display = c_void;
+type Window = c_ulong;
+type GLXContext = *mut c_void;
+
+#[repr(C)]
+struct XVisualInfo {
+    visual: *mut c_void,
+    visualid: c_ulong,
+    screen: c_int,
+    depth: c_int,
+    class: c_int,
+    red_mask: c_ulong,
+    green_mask: c_ulong,
+    blue_mask: c_ulong,
+    colormap_size: c_int,
+    bits_per_rgb: c_int,
+}
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(name: *const c_char) -> *mut Display;
+    fn XCloseDisplay(display: *mut Display) -> c_int;
+    fn XDefaultScreen(display: *mut Display) -> c_int;
+    fn XRootWindow(display: *mut Display, screen: c_int) -> Window;
+    fn XCreateSimpleWindow(
+        display: *mut Display,
+        parent: Window,
+        x: c_int,
+        y: c_int,
+        width: c_uint,
+        height: c_uint,
+        border_width: c_uint,
+        border: c_ulong,
+        background: c_ulong,
+    ) -> Window;
+    fn XMapWindow(display: *mut Display, window: Window) -> c_int;
+    fn XDestroyWindow(display: *mut Display, window: Window) -> c_int;
+    fn XSync(display: *mut Display, discard: c_int) -> c_int;
+    fn XFree(data: *mut c_void) -> c_int;
+    fn XGetImage(
+        display: *mut Display,
+        drawable: Window,
+        x: c_int,
+        y: c_int,
+        width: c_uint,
+        height: c_uint,
+        plane_mask: c_ulong,
+        format: c_int,
+    ) -> *mut c_void;
+    fn XGetPixel(image: *mut c_void, x: c_int, y: c_int) -> c_ulong;
+    fn XDestroyImage(image: *mut c_void) -> c_int;
+}
+
+// defined by this crate's GLX front end, which has no Rust API
+extern "C" {
+    fn glXChooseVisual(
+        display: *mut Display,
+        screen: c_int,
+        attributes: *const c_int,
+    ) -> *mut XVisualInfo;
+    fn glXGetConfig(
+        display: *mut Display,
+        visual: *const XVisualInfo,
+        attribute: c_int,
+        value: *mut c_int,
+    ) -> c_int;
+    fn glXCreateContext(
+        display: *mut Display,
+        visual: *const XVisualInfo,
+        share_list: GLXContext,
+        direct: c_int,
+    ) -> GLXContext;
+    fn glXDestroyContext(display: *mut Display, context: GLXContext);
+    fn glXMakeCurrent(display: *mut Display, drawable: Window, context: GLXContext) -> c_int;
+    fn glXSwapBuffers(display: *mut Display, drawable: Window);
+}
+
+/// Kills the server when the test ends, however it ends.
+struct Xvfb(Child);
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Starts Xvfb on an unused display and connects to it, or returns `None` if it is not installed.
+fn start_xvfb() -> Option<(Xvfb, *mut Display)> {
+    for number in 90..110 {
+        let name = format!(":{number}");
+        if std::path::Path::new(&format!("/tmp/.X11-unix/X{number}")).exists() {
+            continue;
+        }
+        let server = match Command::new("Xvfb")
+            .args([&name, "-screen", "0", "64x64x24", "-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(server) => Xvfb(server),
+            Err(_) => return None,
+        };
+
+        let name = CString::new(name).unwrap();
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            let display = unsafe { XOpenDisplay(name.as_ptr()) };
+            if !display.is_null() {
+                return Some((server, display));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+    panic!("cannot start Xvfb");
+}
+
+#[test]
+fn clear_and_swap_to_a_window() {
+    let Some((_server, display)) = start_xvfb() else {
+        eprintln!("skipping GLX test: Xvfb is not installed");
+        return;
+    };
+
+    unsafe {
+        let screen = XDefaultScreen(display);
+        let attributes = [GLX_RGBA, GLX_DOUBLEBUFFER, GLX_DEPTH_SIZE, 16, GLX_NONE];
+        let visual = glXChooseVisual(display, screen, attributes.as_ptr());
+        assert!(!visual.is_null());
+        let mut double_buffer = 0;
+        assert_eq!(
+            glXGetConfig(display, visual, GLX_DOUBLEBUFFER, &mut double_buffer),
+            0
+        );
+        assert_eq!(double_buffer, 1);
+        assert_ne!(
+            glXGetConfig(display, visual, GLX_DOUBLEBUFFER, std::ptr::null_mut()),
+            0
+        );
+
+        // probing for another format leaves the first visual alone
+        let single_attributes = [GLX_RGBA, GLX_DEPTH_SIZE, 16, GLX_NONE];
+        let single = glXChooseVisual(display, screen, single_attributes.as_ptr());
+        assert!(!single.is_null());
+        assert_eq!(
+            glXGetConfig(display, single, GLX_DOUBLEBUFFER, &mut double_buffer),
+            0
+        );
+        assert_eq!(double_buffer, 0);
+        assert_eq!(
+            glXGetConfig(display, visual, GLX_DOUBLEBUFFER, &mut double_buffer),
+            0
+        );
+        assert_eq!(double_buffer, 1);
+        XFree(single.cast());
+
+        let window =
+            XCreateSimpleWindow(display, XRootWindow(display, screen), 0, 0, 64, 64, 0, 0, 0);
+        XMapWindow(display, window);
+        XSync(display, 0);
+
+        let context = glXCreateContext(display, visual, std::ptr::null_mut(), 1);
+        assert!(!context.is_null());
+        // applications usually free the visual as soon as they have their context
+        XFree(visual.cast());
+        assert_eq!(glXMakeCurrent(display, window, context), 1);
+
+        glClearColor(0.0, 1.0, 0.0, 1.0);
+        glClear(GL_COLOR_BUFFER_BIT);
+        glXSwapBuffers(display, window);
+        XSync(display, 0);
+
+        let image = XGetImage(display, window, 0, 0, 64, 64, ALL_PLANES, Z_PIXMAP);
+        assert!(!image.is_null());
+        assert_eq!(XGetPixel(image, 32, 32) & 0xffffff, 0x00ff00);
+        XDestroyImage(image);
+
+        assert_eq!(glXMakeCurrent(display, 0, std::ptr::null_mut()), 1);
+        glXDestroyContext(display, context);
+        XDestroyWindow(display, window);
+        XCloseDisplay(display);
+    }
+}
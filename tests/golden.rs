@@ -0,0 +1,378 @@
+//! Golden image tests for the rasterizer.
+//!
+//! Each test draws a scene through the GL entry points into an offscreen framebuffer and compares
+//! it with a reference image in `tests/golden`. When a scene stops matching, the rendered image and
+//! a diff, with the differing pixels in red over a dimmed copy of the reference, are written to
+//! `golden` in Cargo's temporary directory for integration tests.
+//!
+//! After a change that is meant to alter the output, run the tests with `MINIGL_BLESS=1` to write
+//! new references, and look them over before committing them.
+
+use std::{env, fs, path::PathBuf};
+
+use opengl32::{
+    image::Image, Capability, ColorBuffer, Context, DepthFunc, MatrixMode, PixelFormat,
+    PrimitiveMode, TexEnvMode, TextureFormat,
+};
+
+const SIZE: usize = 64;
+
+/// The largest difference in any channel for a pixel to still match its reference.
+const TOLERANCE: u8 = 2;
+
+/// How many pixels may differ by more than `TOLERANCE`, so that edges landing on the other side of
+/// a pixel center after a harmless change in rounding do not fail the test.
+const MAX_MISMATCHES: usize = 8;
+
+/// Draws a scene into a single buffered `SIZE` x `SIZE` context cleared to dark blue, with depth
+/// testing off and identity matrices.
+fn render(draw: impl FnOnce(&Context)) -> Image {
+    let format = PixelFormat {
+        double_buffer: false,
+        ..Default::default()
+    };
+    let context = Context::with_format(SIZE, SIZE, format);
+    context.clear_color(0.1, 0.1, 0.2, 1.0);
+    context.clear(true, true, false);
+    draw(&context);
+    context.finish();
+    context.framebuffer(|fb| {
+        let pixels = fb.color_buffer(ColorBuffer::Front);
+        Image::from_bgra(pixels, fb.width, fb.height, fb.width * 4)
+    })
+}
+
+/// Parses a binary PPM as written by `Image::write_pnm`.
+fn parse_ppm(data: &[u8]) -> Option<Image> {
+    // the header is four whitespace separated fields, the last followed by a single whitespace
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while data.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while !data.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        fields.push(std::str::from_utf8(&data[start..pos]).ok()?);
+    }
+    let pixels = &data[pos + 1..];
+
+    let [magic, width, height, max] = fields[..] else {
+        return None;
+    };
+    let (width, height): (usize, usize) = (width.parse().ok()?, height.parse().ok()?);
+    if magic != "P6" || max != "255" || pixels.len() != width * height * 3 {
+        return None;
+    }
+    Some(Image {
+        width,
+        height,
+        channels: 3,
+        data: pixels.to_vec(),
+    })
+}
+
+fn write_ppm(image: &Image, path: &PathBuf) {
+    let mut data = Vec::new();
+    image.write_pnm(&mut data).unwrap();
+    fs::write(path, data).unwrap_or_else(|err| panic!("cannot write {}: {err}", path.display()));
+}
+
+/// Compares `image` with the reference called `name`, or replaces the reference when blessing.
+fn check(name: &str, image: Image) {
+    let references = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let path = references.join(format!("{name}.ppm"));
+    if env::var_os("MINIGL_BLESS").is_some_and(|bless| bless == "1") {
+        fs::create_dir_all(&references).unwrap();
+        write_ppm(&image, &path);
+        return;
+    }
+
+    let data = fs::read(&path).unwrap_or_else(|err| {
+        panic!(
+            "cannot read {}: {err}; run with MINIGL_BLESS=1 to create it",
+            path.display()
+        )
+    });
+    let reference =
+        parse_ppm(&data).unwrap_or_else(|| panic!("{} is not a binary PPM", path.display()));
+    assert_eq!(
+        (image.width, image.height),
+        (reference.width, reference.height),
+        "{name} is not the size of its reference"
+    );
+
+    let mut diff = Vec::with_capacity(reference.data.len());
+    let mut mismatches = 0;
+    for (pixel, expected) in image
+        .data
+        .chunks_exact(3)
+        .zip(reference.data.chunks_exact(3))
+    {
+        let matches = pixel
+            .iter()
+            .zip(expected)
+            .all(|(&a, &b)| a.abs_diff(b) <= TOLERANCE);
+        if matches {
+            let luma =
+                (expected[0] as u32 * 77 + expected[1] as u32 * 150 + expected[2] as u32 * 29) >> 8;
+            diff.extend([(luma / 3) as u8; 3]);
+        } else {
+            mismatches += 1;
+            diff.extend([255, 0, 0]);
+        }
+    }
+    if mismatches <= MAX_MISMATCHES {
+        return;
+    }
+
+    let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out).unwrap();
+    let actual_path = out.join(format!("{name}.ppm"));
+    let diff_path = out.join(format!("{name}-diff.ppm"));
+    write_ppm(&image, &actual_path);
+    let diff = Image {
+        width: reference.width,
+        height: reference.height,
+        channels: 3,
+        data: diff,
+    };
+    write_ppm(&diff, &diff_path);
+    panic!(
+        "{mismatches} pixels of {name} differ from the reference by more than {TOLERANCE}; \
+         wrote {} and {}",
+        actual_path.display(),
+        diff_path.display()
+    );
+}
+
+#[test]
+fn textured_quad() {
+    check(
+        "textured_quad",
+        render(|gl| {
+            // a 4x4 checkerboard with a different color in each corner
+            let mut texels = Vec::new();
+            for y in 0..4 {
+                for x in 0..4 {
+                    let texel = match (x < 2, y < 2, (x + y) % 2 == 0) {
+                        (_, _, false) => [255, 255, 255],
+                        (true, true, true) => [255, 0, 0],
+                        (false, true, true) => [0, 255, 0],
+                        (true, false, true) => [0, 0, 255],
+                        (false, false, true) => [255, 255, 0],
+                    };
+                    texels.extend(texel);
+                }
+            }
+            let texture = gl.gen_texture();
+            gl.bind_texture(texture);
+            gl.tex_image_2d(4, 4, TextureFormat::Rgb, &texels);
+            gl.enable(Capability::Texture2D);
+
+            // the left quad shows the texture as it is and the right one darkened by a gradient
+            gl.tex_env_mode(TexEnvMode::Replace);
+            gl.begin(PrimitiveMode::Quads);
+            gl.tex_coord2(0.0, 0.0);
+            gl.vertex2(-0.9, -0.9);
+            gl.tex_coord2(1.0, 0.0);
+            gl.vertex2(-0.1, -0.9);
+            gl.tex_coord2(1.0, 1.0);
+            gl.vertex2(-0.1, 0.9);
+            gl.tex_coord2(0.0, 1.0);
+            gl.vertex2(-0.9, 0.9);
+            gl.end();
+
+            gl.tex_env_mode(TexEnvMode::Modulate);
+            gl.begin(PrimitiveMode::Quads);
+            gl.color3(1.0, 1.0, 1.0);
+            gl.tex_coord2(0.0, 0.0);
+            gl.vertex2(0.1, -0.9);
+            gl.tex_coord2(2.0, 0.0);
+            gl.vertex2(0.9, -0.9);
+            gl.color3(0.2, 0.2, 0.2);
+            gl.tex_coord2(2.0, 2.0);
+            gl.vertex2(0.9, 0.9);
+            gl.tex_coord2(0.0, 2.0);
+            gl.vertex2(0.1, 0.9);
+            gl.end();
+        }),
+    );
+}
+
+#[test]
+fn clipped_triangles() {
+    check(
+        "clipped_triangles",
+        render(|gl| {
+            gl.matrix_mode(MatrixMode::Projection);
+            gl.frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 10.0);
+            gl.matrix_mode(MatrixMode::ModelView);
+
+            gl.begin(PrimitiveMode::Triangles);
+            // crosses the left, right and top edges of the viewport
+            gl.color3(1.0, 0.5, 0.0);
+            gl.vertex3(-3.0, -1.0, -2.0);
+            gl.vertex3(3.0, -1.0, -2.0);
+            gl.vertex3(0.0, 4.0, -2.0);
+            // runs from behind the far plane to in front of the near one
+            gl.color3(0.0, 0.8, 1.0);
+            gl.vertex3(-0.5, -0.5, -20.0);
+            gl.color3(1.0, 0.0, 1.0);
+            gl.vertex3(0.4, -0.8, -0.5);
+            gl.color3(1.0, 1.0, 0.0);
+            gl.vertex3(-0.2, -0.9, -1.5);
+            gl.end();
+        }),
+    );
+}
+
+#[test]
+fn depth_overlap() {
+    check(
+        "depth_overlap",
+        render(|gl| {
+            gl.enable(Capability::DepthTest);
+            gl.depth_func(DepthFunc::Less);
+
+            // two quads sloping in opposite directions in depth, which cross halfway
+            gl.begin(PrimitiveMode::Quads);
+            gl.color3(1.0, 0.2, 0.2);
+            gl.vertex3(-0.9, -0.6, -0.8);
+            gl.vertex3(0.9, -0.6, 0.8);
+            gl.vertex3(0.9, 0.3, 0.8);
+            gl.vertex3(-0.9, 0.3, -0.8);
+            gl.color3(0.2, 1.0, 0.2);
+            gl.vertex3(-0.9, -0.3, 0.8);
+            gl.vertex3(0.9, -0.3, -0.8);
+            gl.vertex3(0.9, 0.6, -0.8);
+            gl.vertex3(-0.9, 0.6, 0.8);
+            gl.end();
+
+            // a flat triangle in the middle that only passes where it is in front
+            gl.begin(PrimitiveMode::Triangles);
+            gl.color3(0.3, 0.3, 1.0);
+            gl.vertex3(-0.5, -0.9, 0.1);
+            gl.vertex3(0.5, -0.9, 0.1);
+            gl.vertex3(0.0, 0.9, 0.1);
+            gl.end();
+        }),
+    );
+}
+
+#[test]
+fn fan_and_strip() {
+    check(
+        "fan_and_strip",
+        render(|gl| {
+            // a hexagon around a white center in the top half
+            gl.begin(PrimitiveMode::TriangleFan);
+            gl.color3(1.0, 1.0, 1.0);
+            gl.vertex2(0.0, 0.45);
+            for i in 0..=6 {
+                let angle = i as f32 * std::f32::consts::PI / 3.0;
+                let hue = [
+                    [1.0, 0.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                    [0.0, 1.0, 1.0],
+                    [0.0, 0.0, 1.0],
+                    [1.0, 0.0, 1.0],
+                ][i % 6];
+                gl.color3(hue[0], hue[1], hue[2]);
+                gl.vertex2(0.4 * angle.cos(), 0.45 + 0.4 * angle.sin());
+            }
+            gl.end();
+
+            // a zigzag ribbon in the bottom half
+            gl.begin(PrimitiveMode::TriangleStrip);
+            for i in 0..8 {
+                let x = -0.9 + i as f32 * 0.25;
+                let t = i as f32 / 7.0;
+                gl.color3(t, 0.5, 1.0 - t);
+                gl.vertex2(x, if i % 2 == 0 { -0.85 } else { -0.35 });
+            }
+            gl.end();
+
+            // and a long pentagon between them, drawn as a single polygon
+            gl.begin(PrimitiveMode::Polygon);
+            gl.color3(0.9, 0.9, 0.9);
+            gl.vertex2(-0.9, -0.25);
+            gl.vertex2(0.7, -0.25);
+            gl.color3(0.4, 0.4, 0.4);
+            gl.vertex2(0.9, -0.15);
+            gl.vertex2(0.7, -0.05);
+            gl.vertex2(-0.9, -0.05);
+            gl.end();
+        }),
+    );
+}
+
+#[test]
+fn ortho_hud() {
+    check(
+        "ortho_hud",
+        render(|gl| {
+            // a shaded triangle in perspective underneath the HUD
+            gl.matrix_mode(MatrixMode::Projection);
+            gl.frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 10.0);
+            gl.matrix_mode(MatrixMode::ModelView);
+            gl.translate(0.0, 0.0, -3.0);
+            gl.rotate(30.0, 0.0, 0.0, 1.0);
+            gl.begin(PrimitiveMode::Triangles);
+            gl.color3(1.0, 0.0, 0.0);
+            gl.vertex2(-1.5, -1.5);
+            gl.color3(0.0, 1.0, 0.0);
+            gl.vertex2(1.5, -1.5);
+            gl.color3(0.0, 0.0, 1.0);
+            gl.vertex2(0.0, 1.5);
+            gl.end();
+
+            // the HUD is drawn in pixel coordinates, with the origin at the bottom left
+            gl.matrix_mode(MatrixMode::Projection);
+            gl.push_matrix();
+            gl.load_identity();
+            gl.ortho(0.0, SIZE as f64, 0.0, SIZE as f64, -1.0, 1.0);
+            gl.matrix_mode(MatrixMode::ModelView);
+            gl.push_matrix();
+            gl.load_identity();
+
+            // a health bar along the bottom, and a crosshair in the middle
+            gl.begin(PrimitiveMode::Quads);
+            gl.color3(0.2, 0.2, 0.2);
+            gl.vertex2(4.0, 4.0);
+            gl.vertex2(36.0, 4.0);
+            gl.vertex2(36.0, 10.0);
+            gl.vertex2(4.0, 10.0);
+            gl.color3(0.1, 0.9, 0.1);
+            gl.vertex2(5.0, 5.0);
+            gl.vertex2(26.0, 5.0);
+            gl.vertex2(26.0, 9.0);
+            gl.vertex2(5.0, 9.0);
+            gl.color3(1.0, 1.0, 1.0);
+            gl.vertex2(31.0, 28.0);
+            gl.vertex2(33.0, 28.0);
+            gl.vertex2(33.0, 36.0);
+            gl.vertex2(31.0, 36.0);
+            gl.vertex2(28.0, 31.0);
+            gl.vertex2(36.0, 31.0);
+            gl.vertex2(36.0, 33.0);
+            gl.vertex2(28.0, 33.0);
+            gl.end();
+
+            // a minimap in the top right corner, cleared through the scissor box
+            gl.scissor(44, 44, 16, 16);
+            gl.enable(Capability::ScissorTest);
+            gl.clear_color(0.0, 0.3, 0.0, 1.0);
+            gl.clear(true, false, false);
+            gl.disable(Capability::ScissorTest);
+
+            gl.matrix_mode(MatrixMode::ModelView);
+            gl.pop_matrix();
+            gl.matrix_mode(MatrixMode::Projection);
+            gl.pop_matrix();
+        }),
+    );
+}